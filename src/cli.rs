@@ -0,0 +1,174 @@
+use crate::terminal::{self, TerminalPrompt};
+use crate::tui;
+use ant_counter::ledger::{self, LedgerEntry};
+use ant_counter::{Config, CounterApp, Error, Network};
+use clap::{Parser, Subcommand};
+use eyre::{eyre, Result};
+use std::path::PathBuf;
+
+// with no subcommand the app runs interactively as before
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Counts things against a max per period, synced on antnet"
+)]
+pub struct Cli {
+    #[arg(long, help = "Config file to use instead of the default one")]
+    pub config: Option<PathBuf>,
+    #[arg(long, value_enum, help = "Network to use, asked for if not given")]
+    pub network: Option<Network>,
+    #[arg(long, help = "Directory holding the key and local counter files")]
+    pub key_dir: Option<PathBuf>,
+    #[arg(long, help = "Name of the counter to use, which is then selected")]
+    pub counter: Option<String>,
+    #[arg(long, help = "Make fewer network calls, see the battery_saver config")]
+    pub battery_saver: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Add one to the counter")]
+    Increment,
+    #[command(about = "Add a number to the counter")]
+    Add { n: usize },
+    #[command(about = "Take a number off the counter")]
+    Subtract { n: usize },
+    #[command(about = "Show the counter")]
+    Status,
+    #[command(about = "Set the max for a period")]
+    SetMax { max: usize },
+    #[command(about = "Set the counter back to zero")]
+    Reset,
+    #[command(about = "Encrypt a key file saved before key files were encrypted")]
+    EncryptKey,
+    #[command(about = "Rebuild a lost key file from its recovery words")]
+    Recover,
+    #[command(about = "Full screen view of the counter with single key commands")]
+    Tui,
+    #[command(about = "Show what has been paid for scratchpads on each network")]
+    Spend,
+}
+
+impl Cli {
+    // flags given on the command line win over the config file
+    pub fn override_config(&self, config: &mut Config) {
+        if let Some(network) = self.network {
+            config.network = Some(network);
+        }
+        if let Some(key_dir) = &self.key_dir {
+            config.key_dir = key_dir.clone();
+        }
+        if self.battery_saver {
+            config.battery_saver.enabled = true;
+        }
+    }
+}
+
+// loads an existing counter without asking anything, runs one command on it
+// and syncs, if antnet can't be reached the change waits in the journal
+pub async fn run_command(cli: &Cli, config: &Config, command: &Command) -> Result<()> {
+    let mut counter_app = CounterApp::new()?;
+    counter_app.apply_config(config);
+    counter_app.connection_type = config.network.unwrap_or(Network::Antnet).connection_type();
+    counter_app.set_path(&config.key_dir);
+    match command {
+        Command::EncryptKey => return Ok(counter_app.encrypt_key_file(&TerminalPrompt)?),
+        Command::Spend => return print_spend(&ledger::load(&counter_app.ledger_file_path)?),
+        Command::Recover => {
+            counter_app.set_device_id_from_file()?;
+            let mnemonic = terminal::ask_for_mnemonic()?;
+            match counter_app.recover(&mnemonic, &TerminalPrompt).await {
+                Err(error @ Error::ClientInit(_)) => println!("{error}...using local counter"),
+                result => result?,
+            }
+            println!("{}", counter_app.get_counter_state());
+            println!("{}: {}", counter_app.selected, counter_app.counter());
+            return Ok(());
+        }
+        _ => (),
+    }
+    match counter_app.set_key_from_file(&TerminalPrompt) {
+        Err(error @ Error::WrongPassphrase) => {
+            return Err(eyre!(
+                "{error}, the passphrase can also be given in {}",
+                terminal::PASSPHRASE_ENV_VAR
+            ))
+        }
+        Err(Error::NoKeyFile(path)) => {
+            return Err(eyre!(
+                "No key at {path:?}, run without a command to create a counter"
+            ))
+        }
+        result => result?,
+    }
+    counter_app.set_device_id_from_file()?;
+    if let Err(error) = counter_app.load_local() {
+        println!("Could not read local counter: {error}");
+    }
+    match counter_app.connect(true).await {
+        Err(error @ Error::ClientInit(_)) => println!("{error}...using local counter"),
+        result => result?,
+    }
+    if let Some(name) = &cli.counter {
+        counter_app.select_counter(name)?;
+    }
+    if counter_app.counters.reset_if_next_period()? {
+        counter_app.sync_to_antnet().await?;
+    }
+    match command {
+        Command::Increment => counter_app.increment()?,
+        Command::Add { n } => counter_app.add(*n)?,
+        Command::Subtract { n } => counter_app.subtract(*n)?,
+        Command::Tui => return tui::run(&mut counter_app).await,
+        Command::Status => {
+            println!("{}", counter_app.get_counter_state());
+            println!("{}: {}", counter_app.selected, counter_app.counter());
+            return Ok(());
+        }
+        Command::SetMax { max } => counter_app.set_max(*max)?,
+        Command::Reset => counter_app.reset()?,
+        Command::EncryptKey | Command::Recover | Command::Spend => {
+            unreachable!("handled before connecting")
+        }
+    }
+    Ok(counter_app.sync_to_antnet().await?)
+}
+
+fn print_spend(entries: &[LedgerEntry]) -> Result<()> {
+    if entries.is_empty() {
+        println!("Nothing has been paid for yet");
+        return Ok(());
+    }
+    for entry in entries {
+        println!(
+            "{} {} on {}: {} for {}",
+            entry.time.strftime("%Y-%m-%d %H:%M"),
+            entry.operation,
+            entry.network,
+            entry.get_cost()?,
+            entry.address
+        );
+    }
+    for (network, total) in ledger::total_by_network(entries)? {
+        println!("Total on {network}: {total}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let cli =
+            Cli::try_parse_from(["ant-counter", "--network", "local", "set-max", "10"]).unwrap();
+        assert!(matches!(cli.network, Some(Network::Local)));
+        assert!(matches!(cli.command, Some(Command::SetMax { max: 10 })));
+        let cli = Cli::try_parse_from(["ant-counter"]).unwrap();
+        assert!(cli.command.is_none());
+        assert!(Cli::try_parse_from(["ant-counter", "set-max", "-1"]).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::counter::{Counter, Operation};
+use crate::message;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// name given to the counter a new or migrated scratchpad starts with
+pub const DEFAULT_COUNTER_NAME: &str = "default";
+
+// every counter kept under one key, each with its own max, period and history
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct CounterCollection {
+    counters: BTreeMap<String, Counter>,
+}
+
+impl CounterCollection {
+    pub fn new() -> Result<CounterCollection, jiff::Error> {
+        Ok(CounterCollection::from_counter(
+            DEFAULT_COUNTER_NAME,
+            Counter::new()?,
+        ))
+    }
+
+    pub fn from_counter(name: &str, counter: Counter) -> CounterCollection {
+        CounterCollection::from_counters(BTreeMap::from([(name.to_string(), counter)]))
+    }
+
+    pub fn from_counters(counters: BTreeMap<String, Counter>) -> CounterCollection {
+        CounterCollection { counters }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Counter> {
+        self.counters.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.counters.contains_key(name)
+    }
+
+    // there is always at least one counter as the last one can't be removed
+    pub fn first_name(&self) -> &str {
+        self.counters
+            .keys()
+            .next()
+            .expect("a collection always holds a counter")
+    }
+
+    pub fn first(&self) -> &Counter {
+        &self.counters[self.first_name()]
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.counters.keys()
+    }
+
+    pub fn add(&mut self, name: &str) -> Result<()> {
+        if name.is_empty() {
+            return Err(eyre!("Counter name can't be empty"));
+        }
+        if self.contains(name) {
+            return Err(eyre!("There is already a counter called {name}"));
+        }
+        self.counters.insert(name.to_string(), Counter::new()?);
+        Ok(())
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        if to.is_empty() {
+            return Err(eyre!("Counter name can't be empty"));
+        }
+        if self.contains(to) {
+            return Err(eyre!("There is already a counter called {to}"));
+        }
+        let Some(counter) = self.counters.remove(from) else {
+            return Err(eyre!("No counter called {from}"));
+        };
+        self.counters.insert(to.to_string(), counter);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        if !self.contains(name) {
+            return Err(eyre!("No counter called {name}"));
+        }
+        if self.counters.len() == 1 {
+            return Err(eyre!("Can't remove the only counter"));
+        }
+        self.counters.remove(name);
+        Ok(())
+    }
+
+    pub fn apply(&mut self, change: &Change, device_id: &str) -> Result<()> {
+        match change {
+            Change::Update { name, operation } => {
+                let Some(counter) = self.counters.get_mut(name) else {
+                    return Err(eyre!("No counter called {name}"));
+                };
+                counter.apply(operation, device_id)?;
+            }
+            Change::Add(name) => self.add(name)?,
+            Change::Rename { from, to } => self.rename(from, to)?,
+            Change::Remove(name) => self.remove(name)?,
+        }
+        Ok(())
+    }
+
+    // the change that puts back what the given one is about to change, None
+    // if it can't be undone
+    pub fn inverse(&self, change: &Change) -> Option<Change> {
+        match change {
+            Change::Update { name, operation } => Some(Change::Update {
+                name: name.clone(),
+                operation: self.get(name)?.inverse(operation)?,
+            }),
+            Change::Add(name) => Some(Change::Remove(name.clone())),
+            Change::Rename { from, to } => Some(Change::Rename {
+                from: to.clone(),
+                to: from.clone(),
+            }),
+            Change::Remove(_) => None,
+        }
+    }
+
+    // true if this copy already shows the change, so replaying one that
+    // reached antnet without being confirmed does nothing
+    pub fn has_applied(&self, change: &Change) -> bool {
+        match change {
+            Change::Update { name, operation } => self
+                .get(name)
+                .is_some_and(|counter| counter.has_applied(operation)),
+            Change::Add(name) => self.contains(name),
+            Change::Rename { from, to } => !self.contains(from) && self.contains(to),
+            Change::Remove(name) => !self.contains(name),
+        }
+    }
+
+    // true if both copies hold the same counters set up the same way,
+    // whatever their counts
+    pub fn same_settings(&self, other: &CounterCollection) -> bool {
+        self.counters.len() == other.counters.len()
+            && self.counters.iter().all(|(name, counter)| {
+                other
+                    .get(name)
+                    .is_some_and(|other_counter| counter.same_settings(other_counter))
+            })
+    }
+
+    // takes in counts for counters both copies hold, which counters exist is
+    // decided by this copy
+    pub fn merge(&mut self, other: &CounterCollection) -> Result<(), jiff::Error> {
+        for (name, counter) in self.counters.iter_mut() {
+            if let Some(other_counter) = other.counters.get(name) {
+                counter.merge(other_counter)?;
+            }
+        }
+        Ok(())
+    }
+
+    // brings changes made on another copy onto this one, just read from
+    // antnet. an upload can reach antnet without being confirmed, so this has
+    // to be safe to repeat: counts and events are merged in from the other
+    // copy, the rest is replayed in order unless this copy already shows it,
+    // and changes that no longer fit (e.g. to a counter removed on another
+    // device) are dropped
+    pub fn replay(
+        &mut self,
+        journal: &[Change],
+        local: &CounterCollection,
+        device_id: &str,
+    ) -> Result<(), jiff::Error> {
+        // set up the same as there means the whole journal got here
+        let landed = self.same_settings(local);
+        for change in journal {
+            if landed
+                || matches!(change, Change::Update { operation, .. } if operation.merges())
+                || self.has_applied(change)
+            {
+                continue;
+            }
+            if let Err(error) = self.apply(change, device_id) {
+                message!("Skipping offline change {change:?}: {error}");
+            }
+        }
+        self.merge(local)
+    }
+
+    // returns true if any counter moved on to a new period
+    pub fn reset_if_next_period(&mut self) -> Result<bool, jiff::Error> {
+        let mut any_reset = false;
+        for (name, counter) in self.counters.iter_mut() {
+            if counter.reset_if_next_period()? {
+                message!("{name} is in a new period");
+                any_reset = true;
+            }
+        }
+        Ok(any_reset)
+    }
+}
+
+// a change to the collection or one of its counters, journaled the same way
+// as a single counter's operations
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub enum Change {
+    Update { name: String, operation: Operation },
+    Add(String),
+    Rename { from: String, to: String },
+    Remove(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collection_test() {
+        let mut collection = CounterCollection::new().unwrap();
+        assert!(collection.add(DEFAULT_COUNTER_NAME).is_err());
+        assert!(collection.remove(DEFAULT_COUNTER_NAME).is_err());
+        collection.add("coffee").unwrap();
+        collection
+            .apply(
+                &Change::Update {
+                    name: "coffee".to_string(),
+                    operation: Operation::SetMax(3),
+                },
+                "phone",
+            )
+            .unwrap();
+        collection.rename("coffee", "tea").unwrap();
+        assert!(!collection.contains("coffee"));
+        assert_eq!(collection.get("tea").unwrap().max, 3);
+        assert_eq!(collection.get(DEFAULT_COUNTER_NAME).unwrap().max, 0);
+        assert!(collection.rename("tea", DEFAULT_COUNTER_NAME).is_err());
+        collection.remove(DEFAULT_COUNTER_NAME).unwrap();
+        assert_eq!(collection.first_name(), "tea");
+        assert!(collection
+            .apply(&Change::Remove(DEFAULT_COUNTER_NAME.to_string()), "phone")
+            .is_err());
+    }
+
+    #[test]
+    fn merge_test() {
+        let mut network = CounterCollection::new().unwrap();
+        network.add("coffee").unwrap();
+        let mut local = network.clone();
+        let increment = |name: &str| Change::Update {
+            name: name.to_string(),
+            operation: Operation::Increment,
+        };
+        local.apply(&increment("coffee"), "phone").unwrap();
+        local.add("screen time").unwrap();
+        network.apply(&increment("coffee"), "laptop").unwrap();
+        network.merge(&local).unwrap();
+        assert_eq!(network.get("coffee").unwrap().count(), 2);
+        assert!(!network.contains("screen time"));
+    }
+}
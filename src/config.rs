@@ -0,0 +1,267 @@
+use crate::counter::ConnectionType;
+use crate::ledger;
+use crate::message;
+use crate::retry::RetryPolicy;
+use clap::ValueEnum;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// the well known key funded on a local test network
+const LOCAL_PRIVATE_KEY: &str =
+    "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+// read before the wallet key file or prompt, so it never has to be typed
+pub const WALLET_KEY_ENV_VAR: &str = "ANT_COUNTER_WALLET_KEY";
+
+// which network to use, also a command line flag
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Copy, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Antnet,
+    Local,
+}
+
+impl Network {
+    pub fn connection_type(&self) -> ConnectionType {
+        match self {
+            Network::Antnet => ConnectionType::Antnet,
+            Network::Local => ConnectionType::Local,
+        }
+    }
+}
+
+// everything that can be set in the config file, anything left out keeps its
+// default and command line flags win over the file
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: Option<Network>, // asked for if not set
+    pub key_dir: PathBuf,
+    pub content_type: u64,
+    pub retry: RetryConfig,
+    pub battery_saver: BatterySaverConfig,
+    pub wallet: WalletConfig,
+    #[serde(skip_serializing)]
+    sync: Option<SyncConfig>, // replaced by retry, only read to migrate it
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            network: None,
+            key_dir: PathBuf::new(),
+            content_type: 99,
+            retry: RetryConfig::default(),
+            battery_saver: BatterySaverConfig::default(),
+            wallet: WalletConfig::default(),
+            sync: None,
+        }
+    }
+}
+
+// the fixed waits config files set before retry policies, create_delay_secs
+// is no longer needed as a new scratchpad is read back under the verify policy
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(default, deny_unknown_fields)]
+struct SyncConfig {
+    verify_attempts: u32,
+    verify_delay_secs: u64,
+    create_delay_secs: u64,
+}
+
+impl Default for SyncConfig {
+    fn default() -> SyncConfig {
+        SyncConfig {
+            verify_attempts: 2,
+            verify_delay_secs: 30,
+            create_delay_secs: 3,
+        }
+    }
+}
+
+impl SyncConfig {
+    // the same waits with no backoff, the deadline leaves room for the reads
+    fn to_retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.verify_attempts,
+            initial_delay_ms: self.verify_delay_secs * 1000,
+            backoff_factor: 1.0,
+            jitter: 0.0,
+            deadline_secs: (self.verify_attempts as u64 + 1) * self.verify_delay_secs,
+            wait_before_first_attempt: true,
+        }
+    }
+}
+
+// verify is for reading back a write until it shows up, read is for every
+// other get from the network, local networks replicate much faster
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub antnet_verify: RetryPolicy,
+    pub antnet_read: RetryPolicy,
+    pub local_verify: RetryPolicy,
+    pub local_read: RetryPolicy,
+}
+
+impl Default for RetryConfig {
+    fn default() -> RetryConfig {
+        RetryConfig {
+            antnet_verify: RetryPolicy {
+                max_attempts: 5,
+                initial_delay_ms: 5000,
+                backoff_factor: 2.0,
+                jitter: 0.2,
+                deadline_secs: 120,
+                wait_before_first_attempt: true,
+            },
+            antnet_read: RetryPolicy {
+                max_attempts: 3,
+                initial_delay_ms: 1000,
+                backoff_factor: 2.0,
+                jitter: 0.2,
+                deadline_secs: 15,
+                wait_before_first_attempt: false,
+            },
+            local_verify: RetryPolicy {
+                max_attempts: 5,
+                initial_delay_ms: 500,
+                backoff_factor: 2.0,
+                jitter: 0.2,
+                deadline_secs: 15,
+                wait_before_first_attempt: true,
+            },
+            local_read: RetryPolicy {
+                max_attempts: 3,
+                initial_delay_ms: 200,
+                backoff_factor: 2.0,
+                jitter: 0.2,
+                deadline_secs: 5,
+                wait_before_first_attempt: false,
+            },
+        }
+    }
+}
+
+// fewer network round trips at the cost of seeing other devices' changes later
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatterySaverConfig {
+    pub enabled: bool,
+    pub debounce_secs: u64, // changes are uploaded together once none come for this long
+    pub connection_cache_secs: u64, // antnet isn't checked or downloaded again within this
+}
+
+impl Default for BatterySaverConfig {
+    fn default() -> BatterySaverConfig {
+        BatterySaverConfig {
+            enabled: false,
+            debounce_secs: 5,
+            connection_cache_secs: 300,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalletConfig {
+    pub local_private_key: String,         // pays on local networks
+    pub private_key_file: Option<PathBuf>, // read instead of asking for the antnet key
+    pub max_cost: Option<String>, // in tokens, creating a scratchpad quoted above this is refused
+}
+
+impl Default for WalletConfig {
+    fn default() -> WalletConfig {
+        WalletConfig {
+            local_private_key: LOCAL_PRIVATE_KEY.to_string(),
+            private_key_file: None,
+            max_cost: None,
+        }
+    }
+}
+
+impl Config {
+    // a path given on the command line has to exist, the default one is
+    // optional and defaults are used without it
+    pub fn load(path: Option<&Path>) -> Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match get_default_config_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let config_toml = fs::read_to_string(&path)
+            .map_err(|error| eyre!("Could not read config file {path:?}: {error}"))?;
+        let config = Config::parse(&config_toml)
+            .map_err(|error| eyre!("Could not parse config file {path:?}: {error}"))?;
+        message!("Config loaded from: {path:?}");
+        Ok(config)
+    }
+
+    // an old [sync] table sets the verify policy for both networks, a max
+    // cost that isn't an amount is refused here rather than when paying
+    pub fn parse(config_toml: &str) -> Result<Config> {
+        let mut config: Config = toml::from_str(config_toml)?;
+        if let Some(max_cost) = &config.wallet.max_cost {
+            ledger::parse_cost(max_cost)
+                .map_err(|error| eyre!("wallet.max_cost is not valid: {error}"))?;
+        }
+        if let Some(sync) = config.sync.take() {
+            message!("The [sync] config table is replaced by [retry], using its timings for now");
+            config.retry.antnet_verify = sync.to_retry_policy();
+            config.retry.local_verify = sync.to_retry_policy();
+        }
+        Ok(config)
+    }
+}
+
+// e.g. ~/.config/ant-counter/config.toml on linux
+pub fn get_default_config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("ant-counter").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let config = Config::parse(
+            r#"
+            network = "local"
+            content_type = 7
+
+            [retry.antnet_verify]
+            max_attempts = 3
+            initial_delay_ms = 100
+            backoff_factor = 1.5
+            jitter = 0.0
+            deadline_secs = 10
+            wait_before_first_attempt = true
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(config.network, Some(Network::Local)));
+        assert_eq!(config.content_type, 7);
+        assert_eq!(config.retry.antnet_verify.max_attempts, 3);
+        assert_eq!(config.retry.antnet_read, RetryConfig::default().antnet_read);
+        assert_eq!(config.wallet, WalletConfig::default());
+        assert!(!config.battery_saver.enabled);
+        assert!(Config::parse("verify_delay_secs = 5").is_err());
+        // a policy has to be given in full
+        assert!(Config::parse("[retry.local_read]\nmax_attempts = 2").is_err());
+        // files from before retry policies still load
+        let config = Config::parse("[sync]\nverify_attempts = 4\nverify_delay_secs = 10").unwrap();
+        assert_eq!(config.retry.local_verify.max_attempts, 4);
+        assert_eq!(config.retry.antnet_verify.initial_delay_ms, 10000);
+        assert_eq!(config.retry.antnet_read, RetryConfig::default().antnet_read);
+        assert!(config.sync.is_none());
+        let config = Config::parse("[wallet]\nmax_cost = \"2\"").unwrap();
+        assert_eq!(config.wallet.max_cost.as_deref(), Some("2"));
+        assert!(Config::parse("[wallet]\nmax_cost = \"lots\"").is_err());
+        assert!(Config::load(Some(Path::new("no_such_config.toml"))).is_err());
+    }
+}
@@ -1,65 +1,141 @@
-use autonomi::client::payment::PaymentOption;
-use autonomi::client::scratchpad;
-use autonomi::client::scratchpad::Bytes;
-use autonomi::{Client, Network, Scratchpad, SecretKey, Wallet};
-use eyre::Result;
-use jiff::{ToSpan, Zoned};
+use crate::collection::{Change, CounterCollection, DEFAULT_COUNTER_NAME};
+use crate::config::{BatterySaverConfig, Config, RetryConfig, WalletConfig, WALLET_KEY_ENV_VAR};
+use crate::events::{Breakdown, Event};
+use crate::history::{PeriodHistory, PeriodRecord};
+use crate::keyfile;
+use crate::ledger::{self, LedgerEntry, LEDGER_FILE_NAME};
+use crate::message;
+use crate::payload::{decode_counters, encode_counters};
+use crate::period::{weekday_serde, Period};
+use crate::prompt::Prompt;
+use crate::recovery;
+use crate::retry::RetryPolicy;
+use crate::storage::{CountingStorage, MemoryStorage, ScratchpadStorage, StorageBackend};
+use crate::sync_task::{SyncResult, SyncTask};
+use autonomi::client::payment::{PaymentOption, Receipt};
+use autonomi::client::scratchpad::ScratchpadAddress;
+use autonomi::{AttoTokens, Client, Network, Scratchpad, SecretKey, Wallet};
+use eyre::eyre;
+use jiff::civil::Weekday;
+use jiff::Zoned;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
 use std::fmt;
 use std::fs;
-use std::fs::File;
-use std::io::Write;
 use std::io::{self};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
-pub struct LastSixValues {
-    values: Vec<usize>,
+// grow only additions and removals per device, a reset starts a new epoch so
+// concurrent changes and resets from several devices merge the same way everywhere
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+pub struct DeviceCount {
+    epoch: u64,
+    counts: BTreeMap<String, usize>,
+    #[serde(default)]
+    decrements: BTreeMap<String, usize>,
 }
 
-impl LastSixValues {
-    pub fn new() -> LastSixValues {
-        LastSixValues { values: Vec::new() }
+impl DeviceCount {
+    pub fn new() -> DeviceCount {
+        DeviceCount {
+            epoch: 0,
+            counts: BTreeMap::new(),
+            decrements: BTreeMap::new(),
+        }
     }
 
-    pub fn add(&mut self, value: usize) {
-        if self.values.len() >= 6 {
-            self.values.remove(0); // could use VecDeque if performance were an issue
+    pub fn from_counts(epoch: u64, counts: BTreeMap<String, usize>) -> DeviceCount {
+        DeviceCount {
+            epoch,
+            counts,
+            decrements: BTreeMap::new(),
         }
-        self.values.push(value);
     }
 
-    pub fn get_last_value(&self) -> usize {
-        if self.values.len() > 0 {
-            return self.values[self.values.len() - 1];
-        } else {
-            0
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    // never below zero, even if merged removals outnumber additions
+    pub fn total(&self) -> usize {
+        let added: usize = self.counts.values().sum();
+        let removed: usize = self.decrements.values().sum();
+        added.saturating_sub(removed)
+    }
+
+    pub fn increment(&mut self, device_id: &str) {
+        self.add(device_id, 1);
+    }
+
+    pub fn add(&mut self, device_id: &str, n: usize) {
+        *self.counts.entry(device_id.to_string()).or_insert(0) += n;
+    }
+
+    pub fn subtract(&mut self, device_id: &str, n: usize) {
+        *self.decrements.entry(device_id.to_string()).or_insert(0) += n;
+    }
+
+    pub fn reset(&mut self) {
+        self.epoch += 1;
+        self.counts.clear();
+        self.decrements.clear();
+    }
+
+    // the later epoch wins, within the same epoch each device keeps its highest
+    // additions and removals
+    pub fn merge(&mut self, other: &DeviceCount) {
+        if other.epoch > self.epoch {
+            *self = other.clone();
+        } else if other.epoch == self.epoch {
+            merge_max(&mut self.counts, &other.counts);
+            merge_max(&mut self.decrements, &other.decrements);
         }
     }
+}
 
-    pub fn get_mean(&self) -> f64 {
-        let total = self.values.iter().sum::<usize>() as f64;
-        total / self.values.len() as f64
+fn merge_max(own: &mut BTreeMap<String, usize>, other: &BTreeMap<String, usize>) {
+    for (device_id, count) in other {
+        let own_count = own.entry(device_id.clone()).or_insert(0);
+        *own_count = (*own_count).max(*count);
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct Counter {
-    pub count: usize,
+    pub count: DeviceCount,
     pub max: usize,
-    pub last_six_values: LastSixValues,
+    #[serde(default)]
+    pub history: PeriodHistory,
     pub reset_zoned_date_time: Zoned,
+    #[serde(default)]
+    pub period: Period,
+    // resets happen at the same instant whichever zone a device is in
+    #[serde(default = "get_system_time_zone")]
+    pub time_zone: String,
+    #[serde(default = "get_default_week_start", with = "weekday_serde")]
+    pub week_start: Weekday,
+    #[serde(default)]
+    pub events: Vec<Event>, // changes to the count in the current period, oldest first
 }
 
 impl fmt::Display for Counter {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mean = match self.history.get_mean() {
+            Some(mean) => format!("{mean:.1}"),
+            None => "none yet".to_string(),
+        };
         write!(
             f,
-            "Remaining: {} of {}, last weeks total: {}, rolling mean: {}, next reset: {}",
+            "Remaining: {} of {}, last periods total: {}, rolling mean: {}, resets {}, next reset: {}",
             self.number_remaining(),
             self.max,
-            self.last_six_values.get_last_value(),
-            self.last_six_values.get_mean(),
+            self.history.get_last_total(),
+            mean,
+            self.period,
             self.reset_zoned_date_time,
         )
     }
@@ -67,54 +143,307 @@ impl fmt::Display for Counter {
 
 impl Counter {
     pub fn new() -> Result<Counter, jiff::Error> {
-        Ok(Counter {
-            count: 0,
+        let mut counter = Counter {
+            count: DeviceCount::new(),
             max: 0,
-            last_six_values: LastSixValues::new(),
-            reset_zoned_date_time: get_start_of_next_week()?,
-        })
+            history: PeriodHistory::default(),
+            reset_zoned_date_time: Zoned::now(),
+            period: Period::default(),
+            time_zone: get_system_time_zone(),
+            week_start: get_default_week_start(),
+            events: Vec::new(),
+        };
+        counter.update_reset_zoned_date_time()?;
+        Ok(counter)
+    }
+
+    // the current time in the counter's own time zone
+    pub fn now(&self) -> Result<Zoned, jiff::Error> {
+        Zoned::now().in_tz(&self.time_zone)
+    }
+
+    // moves the end of the current period to the next boundary, used when
+    // anything that decides where boundaries fall changes
+    fn update_reset_zoned_date_time(&mut self) -> Result<(), jiff::Error> {
+        self.reset_zoned_date_time = self.period.next_start(&self.now()?, self.week_start)?;
+        Ok(())
     }
 
     pub fn set_max(&mut self, max: usize) {
         self.max = max;
     }
 
+    pub fn set_history_length(&mut self, length: usize) {
+        self.history.set_retention(length);
+    }
+
+    // fails if the period is too long to end anywhere, leaving the counter as it was
+    pub fn set_period(&mut self, period: Period) -> Result<(), jiff::Error> {
+        self.reset_zoned_date_time = period.next_start(&self.now()?, self.week_start)?;
+        self.period = period;
+        Ok(())
+    }
+
+    // fails if the name is not in the time zone database, leaving the counter as it was
+    pub fn set_time_zone(&mut self, time_zone: &str) -> Result<(), jiff::Error> {
+        Zoned::now().in_tz(time_zone)?;
+        self.time_zone = time_zone.to_string();
+        self.update_reset_zoned_date_time()
+    }
+
+    pub fn set_week_start(&mut self, week_start: Weekday) -> Result<(), jiff::Error> {
+        self.week_start = week_start;
+        self.update_reset_zoned_date_time()
+    }
+
     pub fn reset(&mut self) {
-        self.count = 0;
+        self.count.reset();
+        self.events.clear();
     }
 
     pub fn reset_stats(&mut self) {
-        self.last_six_values = LastSixValues::new();
+        self.history.clear();
     }
 
-    // checks if time is past rest_zoned_data_time and if so resets the counter
-    // and updates reset_zoned_date_time to next period start
     pub fn reset_if_next_period(&mut self) -> Result<bool, jiff::Error> {
-        let mut reset = false;
-        let now = Zoned::now();
-        if now > self.reset_zoned_date_time {
-            self.reset();
-            self.last_six_values.add(self.count);
-            self.reset_zoned_date_time = get_start_of_next_week()?;
-            // self.reset_zoned_date_time = get_a_minute_from_now()?;
-            reset = true;
-            println!("Reseting as in new period")
+        let skipped = match self.roll_over(&self.now()?)? {
+            0 => return Ok(false),
+            periods => periods - 1,
+        };
+        message!("Reseting as in new period");
+        if skipped > 0 {
+            message!("{skipped} periods passed with no use");
+        }
+        Ok(true)
+    }
+
+    // checks if now is past rest_zoned_data_time and if so archives the total
+    // for the period that ended, adds a zero for every period that was skipped
+    // entirely, starts a new count and moves reset_zoned_date_time on to the
+    // end of the period now is in. returns how many periods were archived
+    fn roll_over(&mut self, now: &Zoned) -> Result<usize, jiff::Error> {
+        if *now <= self.reset_zoned_date_time {
+            return Ok(0);
+        }
+        let mut period_start = self.reset_zoned_date_time.in_tz(&self.time_zone)?;
+        self.history.add(PeriodRecord {
+            start: self.period.previous_start(&period_start)?,
+            end: period_start.clone(),
+            total: self.count(),
+            max: self.max,
+            breakdown: self.breakdown()?,
+            count: self.count.clone(),
+        });
+        // unlike a reset the new period's count starts afresh rather than in
+        // a later epoch, so counts from copies that rolled over separately
+        // still merge
+        self.count = DeviceCount::new();
+        self.events.clear();
+        let mut periods = 1;
+        let mut period_end = self.period.next_start(&period_start, self.week_start)?;
+        while period_end < *now {
+            self.history.add(PeriodRecord {
+                start: period_start,
+                end: period_end.clone(),
+                total: 0,
+                max: self.max,
+                breakdown: Breakdown::default(),
+                count: DeviceCount::new(),
+            });
+            periods += 1;
+            period_start = period_end;
+            period_end = self.period.next_start(&period_start, self.week_start)?;
         }
-        Ok(reset)
+        self.reset_zoned_date_time = period_end;
+        Ok(periods)
+    }
+
+    pub fn increment(&mut self, device_id: &str) -> Result<(), jiff::Error> {
+        self.count.increment(device_id);
+        self.record_event(device_id, 1)
+    }
+
+    pub fn add(&mut self, device_id: &str, n: usize) -> Result<(), jiff::Error> {
+        self.count.add(device_id, n);
+        self.record_event(device_id, n as isize)
+    }
+
+    // can't take the count below zero
+    pub fn subtract(&mut self, device_id: &str, n: usize) -> Result<(), jiff::Error> {
+        let n = n.min(self.count());
+        self.count.subtract(device_id, n);
+        self.record_event(device_id, -(n as isize))
+    }
+
+    fn record_event(&mut self, device_id: &str, change: isize) -> Result<(), jiff::Error> {
+        self.events.push(Event {
+            time: self.now()?,
+            device_id: device_id.to_string(),
+            epoch: self.count.epoch(),
+            change,
+        });
+        Ok(())
     }
 
-    pub fn increment(&mut self) {
-        self.count += 1;
+    // per day and per hour changes in the current period
+    pub fn breakdown(&self) -> Result<Breakdown, jiff::Error> {
+        Breakdown::from_events(&self.events, &self.time_zone)
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.total()
     }
 
     pub fn number_remaining(&self) -> isize {
-        self.max as isize - self.count as isize
+        self.max as isize - self.count() as isize
+    }
+
+    // takes in counts, events and finished periods from another copy of this
+    // counter, everything else is kept as is, events from before the latest
+    // reset are dropped. both copies are moved on to the current period first
+    // so counts from one that ended are added to its record, not dropped
+    pub fn merge(&mut self, other: &Counter) -> Result<(), jiff::Error> {
+        let now = self.now()?;
+        let mut other = other.clone();
+        other.roll_over(&now)?;
+        self.roll_over(&now)?;
+        self.history.merge(&other.history);
+        self.count.merge(&other.count);
+        for event in &other.events {
+            if !self.events.contains(event) {
+                self.events.push(event.clone());
+            }
+        }
+        let epoch = self.count.epoch();
+        self.events.retain(|event| event.epoch == epoch);
+        self.events.sort_by_key(|event| event.time.timestamp());
+        Ok(())
+    }
+
+    pub fn apply(&mut self, operation: &Operation, device_id: &str) -> Result<(), jiff::Error> {
+        match operation {
+            Operation::Increment => self.increment(device_id)?,
+            Operation::Add(n) => self.add(device_id, *n)?,
+            Operation::Subtract(n) => self.subtract(device_id, *n)?,
+            Operation::Reset => self.reset(),
+            Operation::ResetStats => self.reset_stats(),
+            Operation::SetMax(max) => self.set_max(*max),
+            Operation::SetHistoryLength(length) => self.set_history_length(*length),
+            Operation::SetPeriod(period) => self.set_period(period.clone())?,
+            Operation::SetTimeZone(time_zone) => self.set_time_zone(time_zone)?,
+            Operation::SetWeekStart(week_start) => self.set_week_start(*week_start)?,
+        }
+        Ok(())
+    }
+
+    // true if the operation would leave the counter as it is, changes to the
+    // count are merged instead so never count as applied
+    pub fn has_applied(&self, operation: &Operation) -> bool {
+        match operation {
+            Operation::ResetStats => self.history.records().is_empty(),
+            Operation::SetMax(max) => self.max == *max,
+            Operation::SetHistoryLength(length) => self.history.retention() == *length,
+            Operation::SetPeriod(period) => self.period == *period,
+            Operation::SetTimeZone(time_zone) => self.time_zone == *time_zone,
+            Operation::SetWeekStart(week_start) => self.week_start == *week_start,
+            _ => false,
+        }
+    }
+
+    pub fn same_settings(&self, other: &Counter) -> bool {
+        self.max == other.max
+            && self.history.retention() == other.history.retention()
+            && self.period == other.period
+            && self.time_zone == other.time_zone
+            && self.week_start == other.week_start
+    }
+
+    // the operation that puts back what the given one is about to change, None
+    // if it throws away something that can't be rebuilt
+    pub fn inverse(&self, operation: &Operation) -> Option<Operation> {
+        Some(match operation {
+            Operation::Increment => Operation::Subtract(1),
+            Operation::Add(n) => Operation::Subtract(*n),
+            Operation::Subtract(n) => Operation::Add((*n).min(self.count())),
+            Operation::Reset => Operation::Add(self.count()),
+            Operation::ResetStats => return None,
+            Operation::SetMax(_) => Operation::SetMax(self.max),
+            // periods already trimmed from the history stay gone
+            Operation::SetHistoryLength(_) => Operation::SetHistoryLength(self.history.retention()),
+            Operation::SetPeriod(_) => Operation::SetPeriod(self.period.clone()),
+            Operation::SetTimeZone(_) => Operation::SetTimeZone(self.time_zone.clone()),
+            Operation::SetWeekStart(_) => Operation::SetWeekStart(self.week_start),
+        })
+    }
+}
+
+// a change made to the counter, journaled until it is known to be on antnet
+// so it can be brought onto the network counter when reconnecting
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub enum Operation {
+    Increment,
+    Add(usize),
+    Subtract(usize),
+    Reset,
+    ResetStats,
+    SetMax(usize),
+    SetHistoryLength(usize),
+    SetPeriod(Period),
+    SetTimeZone(String),
+    SetWeekStart(#[serde(with = "weekday_serde")] Weekday),
+}
+
+impl Operation {
+    // changes to the count, which copies take from each other by merging
+    // rather than by being replayed
+    pub fn merges(&self) -> bool {
+        matches!(
+            self,
+            Operation::Increment | Operation::Add(_) | Operation::Subtract(_) | Operation::Reset
+        )
+    }
+}
+
+// what is saved to the local state file. it has no version like the
+// scratchpad payload, so fields added to the counters since have serde
+// defaults for older files to still load, and a file from before named
+// counters is read as LocalStateV1
+#[derive(Serialize, Deserialize)]
+struct LocalState {
+    counters: CounterCollection,
+    selected: String,
+    journal: Vec<Change>,
+}
+
+// a single counter, its journaled operations went to it
+#[derive(Deserialize)]
+struct LocalStateV1 {
+    counter: Counter,
+    journal: Vec<Operation>,
+}
+
+impl From<LocalStateV1> for LocalState {
+    fn from(local_state: LocalStateV1) -> LocalState {
+        let journal = local_state
+            .journal
+            .into_iter()
+            .map(|operation| Change::Update {
+                name: DEFAULT_COUNTER_NAME.to_string(),
+                operation,
+            })
+            .collect();
+        LocalState {
+            counters: CounterCollection::from_counter(DEFAULT_COUNTER_NAME, local_state.counter),
+            selected: DEFAULT_COUNTER_NAME.to_string(),
+            journal,
+        }
     }
 }
 
 pub enum ConnectionType {
     Local,
     Antnet,
+    Memory(MemoryStorage), // for tests and demos, nothing leaves the process
 }
 
 impl ConnectionType {
@@ -122,8 +451,42 @@ impl ConnectionType {
         match self {
             ConnectionType::Local => Path::new("local_key"),
             ConnectionType::Antnet => Path::new("key"),
+            ConnectionType::Memory(_) => Path::new("memory_key"),
         }
     }
+
+    pub fn get_network_name(&self) -> &str {
+        match self {
+            ConnectionType::Local => "local",
+            ConnectionType::Antnet => "antnet",
+            ConnectionType::Memory(_) => "memory",
+        }
+    }
+
+    pub fn get_state_file_name(&self) -> &Path {
+        match self {
+            ConnectionType::Local => Path::new("local_counter.json"),
+            ConnectionType::Antnet => Path::new("counter.json"),
+            ConnectionType::Memory(_) => Path::new("memory_counter.json"),
+        }
+    }
+
+    // connect to whichever storage backend this connection type uses
+    pub async fn init_storage(&self) -> Result<Arc<dyn StorageBackend>> {
+        Ok(match self {
+            ConnectionType::Local => Arc::new(ScratchpadStorage::new(
+                Client::init_local()
+                    .await
+                    .map_err(|error| Error::ClientInit(error.into()))?,
+            )),
+            ConnectionType::Antnet => Arc::new(ScratchpadStorage::new(
+                Client::init()
+                    .await
+                    .map_err(|error| Error::ClientInit(error.into()))?,
+            )),
+            ConnectionType::Memory(storage) => Arc::new(storage.clone()),
+        })
+    }
 }
 
 pub enum CounterState {
@@ -131,7 +494,7 @@ pub enum CounterState {
     Local,
     LocalWithKey(SecretKey),
     Connected {
-        client: Client,
+        storage: Arc<dyn StorageBackend>,
         scratchpad: Scratchpad,
         key: SecretKey,
     },
@@ -155,122 +518,554 @@ impl PartialEq for CounterState {
 pub struct CounterApp {
     pub connection_type: ConnectionType,
     pub counter_state: CounterState,
-    pub counter: Counter,
+    pub counters: CounterCollection,
+    pub selected: String, // which counter operations apply to, only kept on this device
     pub content_type: u64,
+    pub retry: RetryConfig,
+    pub battery_saver: BatterySaverConfig,
+    pub wallet: WalletConfig,
     pub key_file_path: PathBuf,
+    pub state_file_path: PathBuf,
+    pub ledger_file_path: PathBuf, // shared by all networks, entries say which
+    pub journal: Vec<Change>,
+    pub undo_stack: Vec<Change>, // inverses of this session's changes, latest last
+    pub device_id: String,
+    sync_task: Option<SyncTask>, // started on the first queued sync once connected
+    network_calls: Arc<AtomicU64>, // this session's, to see what battery saver saves
+    last_contact: Option<Instant>, // when antnet last answered
+}
+
+// what can go wrong in CounterApp, so a front end can tell e.g. a wrong
+// passphrase from antnet being unreachable
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Could not read or write key file {path:?}: {source}")]
+    KeyFile { path: PathBuf, source: io::Error },
+    #[error("Could not read or write {path:?}: {source}")]
+    LocalFile { path: PathBuf, source: io::Error },
+    #[error("There is already a key file at {0:?}, move it before recovering")]
+    KeyFileExists(PathBuf),
+    #[error("No key file at {0:?}, create or recover a counter first")]
+    NoKeyFile(PathBuf),
+    #[error("Wrong passphrase or damaged key file")]
+    WrongPassphrase,
+    #[error("Not a valid key: {0}")]
+    InvalidKey(eyre::Report),
+    #[error("Could not encrypt key: {0}")]
+    KeyEncryption(eyre::Report),
+    #[error("Could not make a key from recovery words: {0}")]
+    Recovery(eyre::Report),
+    #[error("Could not read input: {0}")]
+    Input(io::Error),
+    #[error("Could not connect to antnet: {0}")]
+    ClientInit(eyre::Report),
+    #[error("Could not serialise counter: {0}")]
+    Serialisation(eyre::Report),
+    #[error("Could not create scratchpad: {0}")]
+    ScratchpadCreate(eyre::Report),
+    #[error("Could not get scratchpad: {0}")]
+    ScratchpadGet(eyre::Report),
+    #[error("Could not update scratchpad: {0}")]
+    ScratchpadUpdate(eyre::Report),
+    #[error("Payment failed: {0}")]
+    Payment(eyre::Report),
+    #[error("Not connected to antnet")]
+    NotConnected,
+    #[error("Background sync has stopped")]
+    SyncStopped,
+    #[error("No counter called {0}")]
+    NoSuchCounter(String),
+    #[error("{0}")]
+    InvalidChange(eyre::Report),
+    #[error("{0}")]
+    Time(#[from] jiff::Error),
 }
 
-// #[derive(Debug, thiserror::Error)]
-// pub enum Error<'a> {
-//     FailedToCreateFile(&'a Path),
-//     FailedToWriteToFile(&'a Path),
-//     FailedToIntiateClient(autonomi::client::ConnectError),
-//     FailedToCreateCounter(jiff::Error),
-//     FailedToSerailzeCounter(bincode::Error),
-//     FailedToCreateScratchPad(autonomi::client::data_types::scratchpad::ScratchpadError),
-//     FailedToGetNetworkCounter,
-// }
+pub type Result<T, E = Error> = std::result::Result<T, E>;
 
 impl CounterApp {
-    pub fn new() -> Result<CounterApp, jiff::Error> {
+    pub fn new() -> Result<CounterApp> {
         Ok(CounterApp {
             connection_type: ConnectionType::Antnet,
             counter_state: CounterState::Initiating,
-            counter: Counter::new()?,
+            counters: CounterCollection::new()?,
+            selected: DEFAULT_COUNTER_NAME.to_string(),
             content_type: 99,
+            retry: RetryConfig::default(),
+            battery_saver: BatterySaverConfig::default(),
+            wallet: WalletConfig::default(),
             key_file_path: PathBuf::new(),
+            state_file_path: PathBuf::new(),
+            ledger_file_path: PathBuf::new(),
+            journal: Vec::new(),
+            undo_stack: Vec::new(),
+            device_id: format!("{:016x}", rand::random::<u64>()),
+            sync_task: None,
+            network_calls: Arc::new(AtomicU64::new(0)),
+            last_contact: None,
         })
     }
 
+    // the key directory is left to set_path as file names depend on the
+    // connection type, which may still be asked for
+    pub fn apply_config(&mut self, config: &Config) {
+        if let Some(network) = config.network {
+            self.connection_type = network.connection_type();
+        }
+        self.content_type = config.content_type;
+        self.retry = config.retry.clone();
+        self.battery_saver = config.battery_saver.clone();
+        self.wallet = config.wallet.clone();
+    }
+
     pub fn set_path(&mut self, path: &Path) {
         self.key_file_path = [path, self.connection_type.get_key_file_name()]
             .iter()
             .collect();
-        println!("Key file path set as: {:?}", self.key_file_path);
+        message!("Key file path set as: {:?}", self.key_file_path);
+        self.state_file_path = [path, self.connection_type.get_state_file_name()]
+            .iter()
+            .collect();
+        self.ledger_file_path = path.join(LEDGER_FILE_NAME);
     }
 
-    pub async fn create(&mut self, private_key: &str) -> Result<()> {
-        // create new key and save to file
-        let key = autonomi::SecretKey::random();
-        let key_hex = key.to_hex();
-        println!("New key: {}", key_hex);
-        let mut file = File::create(&self.key_file_path)?;
-        file.write_all(key_hex.as_bytes())?;
+    // the device id is kept next to the key file so this device keeps counting
+    // under the same id, a new one is saved the first time
+    pub fn set_device_id_from_file(&mut self) -> Result<()> {
+        let device_id_file_path = self.key_file_path.with_file_name("device_id");
+        match fs::read_to_string(&device_id_file_path) {
+            Ok(device_id) => self.device_id = device_id.trim().to_string(),
+            Err(_) => fs::write(&device_id_file_path, &self.device_id).map_err(|source| {
+                Error::LocalFile {
+                    path: device_id_file_path.clone(),
+                    source,
+                }
+            })?,
+        }
+        message!("Device id: {}", self.device_id);
+        Ok(())
+    }
+
+    // keeps a copy of the counter and journal next to the key file so changes
+    // made while not connected survive quitting
+    pub fn save_local(&self) -> Result<()> {
+        let local_state = LocalState {
+            counters: self.counters.clone(),
+            selected: self.selected.clone(),
+            journal: self.journal.clone(),
+        };
+        let local_state_json = serde_json::to_string(&local_state)
+            .map_err(|error| Error::Serialisation(error.into()))?;
+        fs::write(&self.state_file_path, local_state_json).map_err(|source| Error::LocalFile {
+            path: self.state_file_path.clone(),
+            source,
+        })?;
+        Ok(())
+    }
+
+    // returns false if there is no saved counter yet
+    pub fn load_local(&mut self) -> Result<bool> {
+        let Ok(local_state_json) = fs::read_to_string(&self.state_file_path) else {
+            return Ok(false);
+        };
+        let local_state = match serde_json::from_str::<LocalState>(&local_state_json) {
+            Ok(local_state) => local_state,
+            Err(error) => match serde_json::from_str::<LocalStateV1>(&local_state_json) {
+                Ok(local_state) => local_state.into(),
+                Err(_) => {
+                    self.keep_unreadable_local()?;
+                    return Err(Error::Serialisation(error.into()));
+                }
+            },
+        };
+        self.counters = local_state.counters;
+        self.selected = local_state.selected;
+        self.journal = local_state.journal;
+        self.check_selected();
+        message!("Local counter loaded from: {:?}", self.state_file_path);
+        if !self.journal.is_empty() {
+            message!("{} changes waiting to sync to antnet", self.journal.len());
+        }
+        Ok(true)
+    }
+
+    // moves a state file that can't be read out of the way, so the unsynced
+    // changes in it aren't lost when the next save writes a new one
+    fn keep_unreadable_local(&self) -> Result<()> {
+        let unreadable_path = self.state_file_path.with_extension("json.unreadable");
+        fs::rename(&self.state_file_path, &unreadable_path).map_err(|source| Error::LocalFile {
+            path: self.state_file_path.clone(),
+            source,
+        })?;
+        message!("Unreadable local counter kept at: {unreadable_path:?}");
+        Ok(())
+    }
+
+    // the environment variable wins, then the configured file, then the test
+    // key on local networks, otherwise the front end asks for it
+    pub fn get_wallet_private_key(&self, prompt: &dyn Prompt) -> Result<String> {
+        if let Ok(private_key) = env::var(WALLET_KEY_ENV_VAR) {
+            return Ok(private_key.trim().to_string());
+        }
+        if let Some(private_key_file) = &self.wallet.private_key_file {
+            let private_key =
+                fs::read_to_string(private_key_file).map_err(|source| Error::KeyFile {
+                    path: private_key_file.clone(),
+                    source,
+                })?;
+            return Ok(private_key.trim().to_string());
+        }
+        if !matches!(self.connection_type, ConnectionType::Antnet) {
+            return Ok(self.wallet.local_private_key.clone());
+        }
+        let private_key = prompt.ask_for_wallet_private_key().map_err(Error::Input)?;
+        Ok(private_key.trim().to_string())
+    }
+
+    fn write_key_file(&self, key: &SecretKey, prompt: &dyn Prompt) -> Result<()> {
+        let passphrase = prompt.ask_for_new_passphrase().map_err(Error::Input)?;
+        let content =
+            keyfile::encrypt_key(&key.to_hex(), &passphrase).map_err(Error::KeyEncryption)?;
+        fs::write(&self.key_file_path, content).map_err(|source| Error::KeyFile {
+            path: self.key_file_path.clone(),
+            source,
+        })
+    }
+
+    // once the key file is written a wallet or antnet that can't be reached
+    // leaves a local counter with the new key, and is returned as the error
+    pub async fn create(&mut self, private_key: &str, prompt: &dyn Prompt) -> Result<()> {
+        // create new key from recovery words, shown once, and save to file
+        let mnemonic = recovery::generate_mnemonic().map_err(Error::Recovery)?;
+        let key = recovery::key_from_mnemonic(&mnemonic).map_err(Error::Recovery)?;
+        prompt
+            .show_recovery_words(&mnemonic)
+            .map_err(Error::Input)?;
+        self.write_key_file(&key, prompt)?;
         // create local counter
-        self.counter = Counter::new()?;
-        // attempt to creat wallet
-        let wallet = match self.get_funded_wallet(&private_key).await {
-            Err(_) => {
-                println!("Cannot get funds to create wallet.");
+        self.counters = CounterCollection::new()?;
+        self.selected = DEFAULT_COUNTER_NAME.to_string();
+        self.journal.clear();
+        self.save_local()?;
+        // attempt to creat wallet, in memory storage has nothing to pay
+        let wallet = match self.connection_type {
+            ConnectionType::Memory(_) => None,
+            _ => match self.get_wallet(&private_key) {
+                Err(error) => {
+                    self.counter_state = CounterState::LocalWithKey(key);
+                    return Err(error);
+                }
+                Ok(wallet) => Some(wallet),
+            },
+        };
+        // attempt to connect safenet and create new scratch pad
+        let storage = match self.init_storage().await {
+            Ok(storage) => storage,
+            Err(error) => {
                 self.counter_state = CounterState::LocalWithKey(key);
-                return Ok(());
+                return Err(error);
             }
-            Ok(wallet) => wallet,
         };
-        // attempt to connect safenet and create new scratch pad
-        if let Ok(client) = match self.connection_type {
-            ConnectionType::Local => Client::init_local().await,
-            ConnectionType::Antnet => Client::init().await,
-        } {
-            // seralize counter and create scratchpad with it
-            let counter_seralized = bincode::serialize(&self.counter)?;
-            let content = Bytes::from(counter_seralized);
-            // estimate cost
-            let public_key = key.public_key();
-            let cost = client.scratchpad_cost(&public_key).await?;
-            println!("Type yes to confirm creation of scratchpad at cost: {cost}:");
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
-            let input = input.trim();
-            match input {
-                "yes" => {
-                    let payment_option = PaymentOption::from(wallet);
-                    let (cost, addr) = client
-                        .scratchpad_create(&key, self.content_type, &content, payment_option)
-                        .await?;
-                    println!("Scratchpad created, cost: {cost} addr {addr}");
-                    // wait for scratchpad to be replicated
-                    tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-                    let scratchpad = client.scratchpad_get(&addr).await?;
-                    self.counter_state = CounterState::Connected {
-                        client,
-                        scratchpad,
-                        key,
-                    };
+        // seralize counter and create scratchpad with it
+        let content = encode_counters(&self.counters).map_err(Error::Serialisation)?;
+        // estimate cost
+        let public_key = key.public_key();
+        let cost = storage
+            .cost(&public_key)
+            .await
+            .map_err(Error::ScratchpadCreate)?;
+        // a max cost or balance that can't be read only leaves a local counter
+        if let Some(max_cost) = &self.wallet.max_cost {
+            let max_cost = match ledger::parse_cost(max_cost) {
+                Ok(max_cost) => max_cost,
+                Err(error) => {
+                    message!("{error}...using local counter");
+                    self.counter_state = CounterState::LocalWithKey(key);
+                    return Ok(());
                 }
-                _ => {
-                    println!("Scratchpad not created, using local counter");
+            };
+            if cost.as_atto() > max_cost.as_atto() {
+                message!("Scratchpad costs {cost}, over the max cost of {max_cost} in the config...using local counter");
+                self.counter_state = CounterState::LocalWithKey(key);
+                return Ok(());
+            }
+        }
+        // say what is missing rather than letting the payment fail
+        if let Some(wallet) = &wallet {
+            let balance = match wallet.balance_of_tokens().await {
+                Ok(balance) => balance,
+                Err(error) => {
+                    message!("Cannot get wallet balance: {error}...using local counter");
                     self.counter_state = CounterState::LocalWithKey(key);
+                    return Ok(());
                 }
+            };
+            message!("Wallet balance: {}", AttoTokens::from_atto(balance));
+            if balance < cost.as_atto() {
+                let shortfall = AttoTokens::from_atto(cost.as_atto() - balance);
+                message!("The scratchpad costs {cost}, the wallet needs another {shortfall} tokens to pay for it");
+                message!(
+                    "Send them to {} and create the counter again...using local counter",
+                    wallet.address()
+                );
+                self.counter_state = CounterState::LocalWithKey(key);
+                return Ok(());
+            }
+        }
+        let payment_option = match wallet {
+            Some(wallet) => PaymentOption::from(wallet),
+            None => PaymentOption::from(Receipt::new()),
+        };
+        match prompt.confirm_cost(&cost).map_err(Error::Input)? {
+            true => {
+                let (cost, addr) = storage
+                    .create(&key, self.content_type, &content, payment_option)
+                    .await
+                    .map_err(Error::ScratchpadCreate)?;
+                message!("Scratchpad created, cost: {cost} addr {addr}");
+                let network = self.connection_type.get_network_name();
+                let entry = LedgerEntry::new("create", network, &cost, &addr.to_string());
+                if let Err(error) = ledger::append(&self.ledger_file_path, &entry) {
+                    message!("Could not record payment in ledger: {error}");
+                }
+                // read it back once replicated
+                let scratchpad = self
+                    .get_verify_policy()
+                    .retry("Reading new scratchpad", || storage.get(&addr))
+                    .await
+                    .map_err(Error::ScratchpadGet)?;
+                self.counter_state = CounterState::Connected {
+                    storage,
+                    scratchpad,
+                    key,
+                };
+            }
+            false => {
+                message!("Scratchpad not created, using local counter");
+                self.counter_state = CounterState::LocalWithKey(key);
             }
-            return Ok(());
         }
-        println!("Cannot connect to antnet to create scratchpad...using local counter");
-        self.counter_state = CounterState::LocalWithKey(key);
         Ok(())
     }
 
     pub fn set_key_from_hex(&mut self, hex_key: &str) -> Result<()> {
-        self.counter_state = CounterState::LocalWithKey(SecretKey::from_hex(&hex_key)?);
-        if let Some(key) = self.get_key() {
-            println!("Key loaded: {}", key.to_hex());
+        let key = SecretKey::from_hex(&hex_key).map_err(|error| Error::InvalidKey(error.into()))?;
+        self.counter_state = CounterState::LocalWithKey(key);
+        Ok(())
+    }
+
+    // rebuilds the key file from the recovery words and connects to the
+    // scratchpad it belongs to, won't write over an existing key file
+    pub async fn recover(
+        &mut self,
+        mnemonic: &recovery::Mnemonic,
+        prompt: &dyn Prompt,
+    ) -> Result<()> {
+        if self.key_file_path.exists() {
+            return Err(Error::KeyFileExists(self.key_file_path.clone()));
+        }
+        let key = recovery::key_from_mnemonic(mnemonic).map_err(Error::Recovery)?;
+        self.write_key_file(&key, prompt)?;
+        message!("Key file rebuilt: {:?}", self.key_file_path);
+        self.counter_state = CounterState::LocalWithKey(key);
+        self.connect(true).await
+    }
+
+    // asks for the passphrase if the key file is encrypted
+    pub fn set_key_from_file(&mut self, prompt: &dyn Prompt) -> Result<()> {
+        let content = fs::read(&self.key_file_path).map_err(|source| match source.kind() {
+            io::ErrorKind::NotFound => Error::NoKeyFile(self.key_file_path.clone()),
+            _ => Error::KeyFile {
+                path: self.key_file_path.clone(),
+                source,
+            },
+        })?;
+        if keyfile::is_encrypted(&content) {
+            let passphrase = prompt.ask_for_passphrase().map_err(Error::Input)?;
+            let key_hex = keyfile::decrypt_key(&content, &passphrase).map_err(|error| {
+                match error.is::<keyfile::WrongPassphrase>() {
+                    true => Error::WrongPassphrase,
+                    false => Error::InvalidKey(error),
+                }
+            })?;
+            self.set_key_from_hex(&key_hex)?;
+        } else {
+            let key_hex =
+                String::from_utf8(content).map_err(|error| Error::InvalidKey(error.into()))?;
+            self.set_key_from_hex(key_hex.trim())?;
+            message!("Key file is not encrypted, use encrypt-key to protect it");
         }
         Ok(())
     }
 
-    pub fn set_key_from_file(&mut self) -> Result<()> {
-        if let Ok(hex_key) = fs::read_to_string(&self.key_file_path) {
-            self.set_key_from_hex(&hex_key)?;
+    // rewrites a plain hex key file from before encryption, the new file is
+    // written alongside and renamed over the old so the key can't be lost halfway
+    pub fn encrypt_key_file(&self, prompt: &dyn Prompt) -> Result<()> {
+        let key_file_error = |source| Error::KeyFile {
+            path: self.key_file_path.clone(),
+            source,
+        };
+        let content = fs::read(&self.key_file_path).map_err(key_file_error)?;
+        if keyfile::is_encrypted(&content) {
+            message!("Key file is already encrypted");
+            return Ok(());
         }
+        let key_hex =
+            String::from_utf8(content).map_err(|error| Error::InvalidKey(error.into()))?;
+        let key_hex = key_hex.trim();
+        // don't encrypt something that isn't a key
+        SecretKey::from_hex(key_hex).map_err(|error| Error::InvalidKey(error.into()))?;
+        let passphrase = prompt.ask_for_new_passphrase().map_err(Error::Input)?;
+        let encrypted_file_path = self.key_file_path.with_extension("encrypted");
+        let encrypted = keyfile::encrypt_key(key_hex, &passphrase).map_err(Error::KeyEncryption)?;
+        fs::write(&encrypted_file_path, encrypted).map_err(key_file_error)?;
+        fs::rename(&encrypted_file_path, &self.key_file_path).map_err(key_file_error)?;
+        message!("Key file encrypted: {:?}", self.key_file_path);
         Ok(())
     }
 
-    pub fn increment(&mut self) {
-        self.counter.increment();
+    // the selected counter, or the first if it has gone since it was selected
+    pub fn counter(&self) -> &Counter {
+        self.counters
+            .get(&self.selected)
+            .unwrap_or_else(|| self.counters.first())
     }
 
-    pub fn reset(&mut self) {
-        self.counter.reset();
+    // falls back to the first counter if the selected one was removed or
+    // renamed, e.g. on another device
+    fn check_selected(&mut self) {
+        if !self.counters.contains(&self.selected) {
+            self.selected = self.counters.first_name().to_string();
+            message!("Selected counter: {}", self.selected);
+        }
+    }
+
+    pub fn select_counter(&mut self, name: &str) -> Result<()> {
+        if !self.counters.contains(name) {
+            return Err(Error::NoSuchCounter(name.to_string()));
+        }
+        self.selected = name.to_string();
+        self.save_local()
+    }
+
+    // every change is journaled until an upload is confirmed, so it can be
+    // replayed if the upload fails or the change was made offline
+    pub fn apply_change(&mut self, change: Change) -> Result<()> {
+        let inverse = self.counters.inverse(&change);
+        self.counters
+            .apply(&change, &self.device_id)
+            .map_err(Error::InvalidChange)?;
+        self.journal.push(change);
+        // nothing before a change that can't be undone can be undone either
+        match inverse {
+            Some(inverse) => self.undo_stack.push(inverse),
+            None => self.undo_stack.clear(),
+        }
+        Ok(())
+    }
+
+    // reverses the latest change made this session that hasn't been undone,
+    // returns false if there is nothing to undo
+    pub fn undo(&mut self) -> Result<bool> {
+        let Some(change) = self.undo_stack.pop() else {
+            return Ok(false);
+        };
+        self.counters
+            .apply(&change, &self.device_id)
+            .map_err(Error::InvalidChange)?;
+        // the selection follows a rename back, or moves off an added counter
+        if let Change::Rename { from, to } = &change {
+            if self.selected == *from {
+                self.selected = to.clone();
+            }
+        }
+        self.journal.push(change);
+        self.check_selected();
+        Ok(true)
+    }
+
+    // applies an operation to the selected counter
+    pub fn apply(&mut self, operation: Operation) -> Result<()> {
+        self.apply_change(Change::Update {
+            name: self.selected.clone(),
+            operation,
+        })
+    }
+
+    pub fn add_counter(&mut self, name: &str) -> Result<()> {
+        self.apply_change(Change::Add(name.to_string()))?;
+        self.selected = name.to_string();
+        Ok(())
+    }
+
+    pub fn rename_counter(&mut self, from: &str, to: &str) -> Result<()> {
+        self.apply_change(Change::Rename {
+            from: from.to_string(),
+            to: to.to_string(),
+        })?;
+        if self.selected == from {
+            self.selected = to.to_string();
+        }
+        Ok(())
+    }
+
+    pub fn remove_counter(&mut self, name: &str) -> Result<()> {
+        self.apply_change(Change::Remove(name.to_string()))?;
+        self.check_selected();
+        Ok(())
+    }
+
+    pub fn increment(&mut self) -> Result<()> {
+        self.apply(Operation::Increment)
+    }
+
+    pub fn add(&mut self, n: usize) -> Result<()> {
+        self.apply(Operation::Add(n))
+    }
+
+    pub fn subtract(&mut self, n: usize) -> Result<()> {
+        self.apply(Operation::Subtract(n))
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        self.apply(Operation::Reset)
+    }
+
+    pub fn reset_stats(&mut self) -> Result<()> {
+        self.apply(Operation::ResetStats)
+    }
+
+    pub fn set_max(&mut self, max: usize) -> Result<()> {
+        self.apply(Operation::SetMax(max))
+    }
+
+    pub fn set_history_length(&mut self, length: usize) -> Result<()> {
+        self.apply(Operation::SetHistoryLength(length))
+    }
+
+    pub fn set_period(&mut self, period: Period) -> Result<()> {
+        self.apply(Operation::SetPeriod(period))
+    }
+
+    pub fn set_time_zone(&mut self, time_zone: &str) -> Result<()> {
+        self.apply(Operation::SetTimeZone(time_zone.to_string()))
+    }
+
+    pub fn set_week_start(&mut self, week_start: Weekday) -> Result<()> {
+        self.apply(Operation::SetWeekStart(week_start))
+    }
+
+    // brings the journal onto the counters just downloaded, returns true if
+    // there was anything to bring
+    fn replay_journal(&mut self, local: &CounterCollection) -> Result<bool> {
+        if self.journal.is_empty() {
+            return Ok(false);
+        }
+        self.counters
+            .replay(&self.journal, local, &self.device_id)?;
+        message!("Brought over {} offline changes", self.journal.len());
+        self.check_selected();
+        Ok(true)
     }
 
     pub fn get_counter_state(&self) -> &str {
@@ -285,10 +1080,10 @@ impl CounterApp {
 
     pub fn print_scratchpad(&self) -> Result<()> {
         if let CounterState::Connected { scratchpad, .. } = &self.counter_state {
-            println!(
+            message!(
                 "scratchpad version {:?}, value: {:?}",
                 scratchpad.counter(),
-                self.counter
+                self.counters
             );
         }
         Ok(())
@@ -302,12 +1097,15 @@ impl CounterApp {
         }
     }
 
-    // try and connect to existing scratchpad
+    // try and connect to existing scratchpad, if antnet can't be reached the
+    // counter is left local with the key and the error returned
     pub async fn connect(&mut self, first_time: bool) -> Result<()> {
+        // the journal has to be up to date before it is replayed
+        self.finish_sync().await?;
         let Some(key) = self.get_key() else {
             match self.counter_state {
                 CounterState::Initiating => {
-                    println!("No key is loaded");
+                    message!("No key is loaded");
                     return Ok(());
                 }
                 _ => return Ok(()), // if they was never a key it just continues to run locally
@@ -315,27 +1113,39 @@ impl CounterApp {
         };
         let key = key.clone();
         let public_key = key.public_key();
-        let Ok(client) = (match self.connection_type {
-            ConnectionType::Local => Client::init_local().await,
-            ConnectionType::Antnet => Client::init().await,
-        }) else {
-            println!("Can't connect to antnet...using local counter");
-            self.counter_state = CounterState::LocalWithKey(key);
-            return Ok(());
+        self.sync_task = None; // the next one is started for the new connection
+        let storage = match self.init_storage().await {
+            Ok(storage) => storage,
+            Err(error) => {
+                self.counter_state = CounterState::LocalWithKey(key);
+                return Err(error);
+            }
         };
-        let Ok(scratchpad) = client.scratchpad_get_from_public_key(&public_key).await else {
-            println!("No scratchpad with that key on antnet...using local counter");
+        let address = ScratchpadAddress::new(public_key);
+        let read = self
+            .get_read_policy()
+            .retry("Reading scratchpad", || storage.get(&address))
+            .await;
+        let Ok(scratchpad) = read else {
+            message!("No scratchpad with that key on antnet...using local counter");
             self.counter_state = CounterState::Local;
             return Ok(());
         };
-        self.counter = bincode::deserialize(&scratchpad.decrypt_data(&key)?)?;
+        let content = scratchpad
+            .decrypt_data(&key)
+            .map_err(|error| Error::ScratchpadGet(error.into()))?;
+        let network_counters = decode_counters(&content).map_err(Error::Serialisation)?;
+        let local_counters = std::mem::replace(&mut self.counters, network_counters);
         self.counter_state = CounterState::Connected {
-            client,
+            storage,
             scratchpad,
             key: key.clone(),
         };
-        // sync the new counter value by uploading and downloading,
-        if !first_time {
+        self.last_contact = Some(Instant::now());
+        // replay anything done while not connected then sync the new counter
+        // value by uploading and downloading,
+        let replayed = self.replay_journal(&local_counters)?;
+        if !first_time || replayed {
             self.upload().await?;
             self.download().await?;
         }
@@ -348,88 +1158,172 @@ impl CounterApp {
         self.counter_state = CounterState::LocalWithKey(key.clone());
     }
 
-    pub async fn get_network_counter(&self) -> Result<Counter> {
+    // network reads and checks of writes are retried as set per network
+    fn get_read_policy(&self) -> &RetryPolicy {
+        match self.connection_type {
+            ConnectionType::Antnet => &self.retry.antnet_read,
+            _ => &self.retry.local_read,
+        }
+    }
+
+    fn get_verify_policy(&self) -> &RetryPolicy {
+        match self.connection_type {
+            ConnectionType::Antnet => &self.retry.antnet_verify,
+            _ => &self.retry.local_verify,
+        }
+    }
+
+    pub async fn get_network_counters(&self) -> Result<CounterCollection> {
         let CounterState::Connected {
-            client,
+            storage,
             scratchpad,
             key,
         } = &self.counter_state
         else {
-            println!("Can't get network counter");
-            return Err(scratchpad::ScratchpadError::Missing.into()); // replace with local error
+            return Err(Error::NotConnected);
         };
-        let counter = bincode::deserialize(
-            &client
-                .scratchpad_get(&scratchpad.address())
-                .await?
-                .decrypt_data(&key)?,
-        )?;
-        Ok(counter)
+        let content = storage
+            .get(scratchpad.address())
+            .await
+            .map_err(Error::ScratchpadGet)?
+            .decrypt_data(&key)
+            .map_err(|error| Error::ScratchpadGet(error.into()))?;
+        decode_counters(&content).map_err(Error::Serialisation)
     }
 
     pub async fn upload(&mut self) -> Result<()> {
-        let counter = self.counter.clone();
-        let counter_serailzed = bincode::serialize(&self.counter)?;
-        let content = Bytes::from(counter_serailzed);
+        let counters = self.counters.clone();
+        let content = encode_counters(&self.counters).map_err(Error::Serialisation)?;
         let CounterState::Connected {
-            client,
+            storage,
             scratchpad: _,
             key,
         } = &self.counter_state
         else {
-            println!("Not connected");
+            message!("Not connected");
             return Ok(());
         };
-        println!("Uploading to antnet...");
-        client
-            .scratchpad_update(&key, self.content_type, &content)
-            .await?;
-        for i in (1..3).step_by(1) {
-            tokio::time::sleep(tokio::time::Duration::from_secs(30)).await;
-            println!("Checking antnet count matches attempt {i}...");
-            if counter == self.get_network_counter().await? {
-                println!("Synced");
-                return Ok(());
+        message!("Uploading to antnet...");
+        storage
+            .update(&key, self.content_type, &content)
+            .await
+            .map_err(Error::ScratchpadUpdate)?;
+        // in battery saver mode a write antnet took recently is trusted to be there
+        let verified = match self.trusts_connection() {
+            true => Ok(()),
+            false => {
+                self.get_verify_policy()
+                    .retry("Checking antnet count matches", || async {
+                        match counters == self.get_network_counters().await? {
+                            true => Ok(()),
+                            false => Err(eyre!("Antnet count doesn't match yet")),
+                        }
+                    })
+                    .await
             }
+        };
+        if verified.is_ok() {
+            message!("Synced");
+            self.last_contact = Some(Instant::now());
+            self.journal.clear();
+            self.save_local()?;
+            return Ok(());
         }
-        println!("Could not sync to antnet, reverting to local counter");
+        message!("Could not sync to antnet, reverting to local counter");
         self.counter_state = CounterState::LocalWithKey(key.clone());
         Ok(())
     }
 
     pub async fn download(&mut self) -> Result<()> {
+        // changes still on their way up would be replaced by the older copy
+        if !self.journal.is_empty() || self.trusts_connection() {
+            return Ok(());
+        }
+        let read = self.get_read_policy().clone();
         let CounterState::Connected {
-            client,
+            storage,
             scratchpad,
-            key,
-        } = &mut self.counter_state
+            ..
+        } = &self.counter_state
         else {
-            println!("Not connected to antnet");
+            message!("Not connected to antnet");
             return Ok(());
         };
         let addr = scratchpad.address();
-        let scratchpad = client.scratchpad_get(addr).await?;
-        self.counter = bincode::deserialize(&scratchpad.decrypt_data(&key)?)?;
-        self.counter_state = CounterState::Connected {
-            client: client.clone(),
-            scratchpad,
-            key: key.clone(),
+        let scratchpad = read
+            .retry("Reading scratchpad", || storage.get(addr))
+            .await
+            .map_err(Error::ScratchpadGet)?;
+        self.take_network_scratchpad(scratchpad)
+    }
+
+    // the network copy replaces this one, as there is nothing in the journal
+    // it only differs by what other devices have done
+    fn take_network_scratchpad(&mut self, scratchpad: Scratchpad) -> Result<()> {
+        let CounterState::Connected {
+            scratchpad: held,
+            key,
+            ..
+        } = &mut self.counter_state
+        else {
+            return Ok(());
         };
+        let content = scratchpad
+            .decrypt_data(key)
+            .map_err(|error| Error::ScratchpadGet(error.into()))?;
+        let mut network_counters = decode_counters(&content).map_err(Error::Serialisation)?;
+        // another device has uploaded since, keep anything counted here as well
+        if scratchpad.counter() > held.counter() {
+            network_counters.merge(&self.counters)?;
+        }
+        *held = scratchpad;
+        self.counters = network_counters;
+        self.last_contact = Some(Instant::now());
+        self.check_selected();
         Ok(())
     }
 
+    // in battery saver mode antnet having answered recently is taken to mean
+    // it still would and that nothing else has changed the scratchpad since
+    fn trusts_connection(&self) -> bool {
+        let cache = Duration::from_secs(self.battery_saver.connection_cache_secs);
+        self.battery_saver.enabled
+            && self
+                .last_contact
+                .is_some_and(|last_contact| last_contact.elapsed() < cache)
+    }
+
+    pub fn get_network_calls(&self) -> u64 {
+        self.network_calls.load(Ordering::Relaxed)
+    }
+
+    // every backend is wrapped so network calls are counted
+    async fn init_storage(&self) -> Result<Arc<dyn StorageBackend>> {
+        let storage = self.connection_type.init_storage().await?;
+        Ok(Arc::new(CountingStorage::new(
+            storage,
+            self.network_calls.clone(),
+        )))
+    }
+
     // this interprets any error as inditive of not being connected hence false returned iseteaf of result
     // changes coutner state from connected to LocalWithKey if fails
     pub async fn is_connected(&mut self) -> bool {
+        if self.get_counter_state() == "Connected" && self.trusts_connection() {
+            return true;
+        }
         let mut connected = false;
         match &self.counter_state {
             CounterState::Connected {
-                client,
+                storage,
                 scratchpad,
                 key,
             } => {
-                connected = client
-                    .scratchpad_check_existance(scratchpad.address())
+                connected = self
+                    .get_read_policy()
+                    .retry("Checking connection", || {
+                        storage.exists(scratchpad.address())
+                    })
                     .await
                     .unwrap_or(false);
                 if connected == false {
@@ -438,11 +1332,15 @@ impl CounterApp {
             }
             _ => (),
         }
+        if connected {
+            self.last_contact = Some(Instant::now());
+        }
         connected
     }
 
     pub async fn sync_to_antnet(&mut self) -> Result<()> {
-        println!("{}", self.counter);
+        message!("{}: {}", self.selected, self.counter());
+        self.save_local()?;
         if self.is_connected().await {
             self.upload().await?;
             self.download().await?; // so local scratchpad synced
@@ -451,63 +1349,615 @@ impl CounterApp {
         Ok(())
     }
 
-    async fn get_funded_wallet(&mut self, private_key: &str) -> Result<Wallet> {
+    // saves locally and hands a snapshot to the background sync task so the
+    // caller doesn't wait on antnet, poll_sync says how it went
+    pub fn queue_sync(&mut self) -> Result<()> {
+        self.save_local()?;
+        let journal = self.journal.clone();
+        let counters = self.counters.clone();
+        let read_back = !self.trusts_connection();
+        let seen = match &self.counter_state {
+            CounterState::Connected { scratchpad, .. } => scratchpad.counter(),
+            _ => 0,
+        };
+        match self.get_sync_task() {
+            Some(sync_task) => sync_task
+                .queue(counters, journal, seen, read_back)
+                .map_err(|_| Error::SyncStopped),
+            None => Ok(()),
+        }
+    }
+
+    // picks up other devices' changes in the background, poll_sync applies
+    // them, unless there are changes here still to go up
+    pub fn refresh(&mut self) -> Result<()> {
+        if !self.journal.is_empty() || self.trusts_connection() {
+            return Ok(());
+        }
+        match self.get_sync_task() {
+            Some(sync_task) => sync_task.refresh().map_err(|_| Error::SyncStopped),
+            None => Ok(()),
+        }
+    }
+
+    // started on first use once connected, None if not connected
+    fn get_sync_task(&mut self) -> Option<&mut SyncTask> {
+        let CounterState::Connected { storage, key, .. } = &self.counter_state else {
+            return None;
+        };
+        if self.sync_task.is_none() {
+            let read = self.get_read_policy().clone();
+            let verify = self.get_verify_policy().clone();
+            let debounce = match self.battery_saver.enabled {
+                true => Duration::from_secs(self.battery_saver.debounce_secs),
+                false => Duration::ZERO,
+            };
+            self.sync_task = Some(SyncTask::spawn(
+                storage.clone(),
+                key.clone(),
+                self.device_id.clone(),
+                self.content_type,
+                read,
+                verify,
+                debounce,
+            ));
+        }
+        self.sync_task.as_mut()
+    }
+
+    pub fn is_syncing(&self) -> bool {
+        self.sync_task
+            .as_ref()
+            .is_some_and(|sync_task| !sync_task.is_idle())
+    }
+
+    // applies a finished background sync if there is one, doesn't wait
+    pub fn poll_sync(&mut self) -> Result<Option<SyncResult>> {
+        let Some(result) = self.sync_task.as_mut().and_then(SyncTask::try_result) else {
+            return Ok(None);
+        };
+        self.apply_sync_result(&result)?;
+        Ok(Some(result))
+    }
+
+    // waits for everything queued to reach antnet, returns the last result
+    pub async fn finish_sync(&mut self) -> Result<Option<SyncResult>> {
+        let mut last_result = None;
+        while let Some(sync_task) = &mut self.sync_task {
+            let Some(result) = sync_task.next_result().await else {
+                break;
+            };
+            self.apply_sync_result(&result)?;
+            last_result = Some(result);
+        }
+        Ok(last_result)
+    }
+
+    fn apply_sync_result(&mut self, result: &SyncResult) -> Result<()> {
+        match result {
+            SyncResult::Synced {
+                scratchpad,
+                counters,
+                journal_len,
+            } => {
+                // anything journaled after the snapshot still has to go up
+                self.journal.drain(..*journal_len.min(&self.journal.len()));
+                // another device's changes went up with it, what has been
+                // done here since goes on top
+                if let Some(counters) = counters {
+                    let local = std::mem::replace(&mut self.counters, (**counters).clone());
+                    self.counters
+                        .replay(&self.journal, &local, &self.device_id)?;
+                    self.check_selected();
+                }
+                if let (
+                    Some(scratchpad),
+                    CounterState::Connected {
+                        scratchpad: held, ..
+                    },
+                ) = (scratchpad, &mut self.counter_state)
+                {
+                    *held = (**scratchpad).clone();
+                }
+                self.last_contact = Some(Instant::now());
+                self.save_local()
+            }
+            SyncResult::Refreshed(scratchpad) => {
+                // changes made since it was asked for go up first, and the
+                // upload merges in whatever this would have brought
+                if !self.journal.is_empty() {
+                    return Ok(());
+                }
+                self.take_network_scratchpad((**scratchpad).clone())?;
+                self.save_local()
+            }
+            SyncResult::Failed(_) => {
+                self.sync_task = None;
+                if let Some(key) = self.get_key() {
+                    self.counter_state = CounterState::LocalWithKey(key.clone());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn get_wallet(&self, private_key: &str) -> Result<Wallet> {
         let local = match self.connection_type {
             ConnectionType::Antnet => false,
-            ConnectionType::Local => true,
+            ConnectionType::Local | ConnectionType::Memory(_) => true,
         };
-        let network = Network::new(local)?;
-        let wallet = Wallet::new_from_private_key(network, private_key)?;
-        println!("Wallet address: {}", wallet.address());
-        println!("Wallet ballance: {}", wallet.balance_of_tokens().await?);
+        let network = Network::new(local).map_err(|error| Error::Payment(error.into()))?;
+        let wallet = Wallet::new_from_private_key(network, private_key)
+            .map_err(|error| Error::Payment(error.into()))?;
+        message!("Wallet address: {}", wallet.address());
         Ok(wallet)
     }
 }
 
-fn get_start_of_next_week() -> Result<Zoned, jiff::Error> {
-    let now = Zoned::now().start_of_day()?;
-    let days_to_next_week = 7 - now.weekday().to_monday_zero_offset();
-    Ok(&now + days_to_next_week.days())
+// falls back to UTC if the device's zone has no IANA name
+pub fn get_system_time_zone() -> String {
+    Zoned::now()
+        .time_zone()
+        .iana_name()
+        .unwrap_or("UTC")
+        .to_string()
 }
 
-// alternate period for testing
-fn get_n_minutes_from_now() -> Result<Zoned, jiff::Error> {
-    let now = Zoned::now();
-    Ok(&now + 10.minute())
+fn get_default_week_start() -> Weekday {
+    Weekday::Monday
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use jiff::ToSpan;
+
+    #[test]
+    fn rollover_test() {
+        let mut counter = Counter::new().unwrap();
+        counter.set_time_zone("UTC").unwrap();
+        let next_reset = counter.reset_zoned_date_time.clone();
+        assert!(!counter.reset_if_next_period().unwrap());
+        // last opened three weeks ago
+        counter.reset_zoned_date_time = &next_reset - 3.weeks();
+        counter.increment("phone").unwrap();
+        counter.increment("phone").unwrap();
+        assert!(counter.reset_if_next_period().unwrap());
+        let totals: Vec<usize> = counter.history.records().iter().map(|r| r.total).collect();
+        assert_eq!(totals, vec![2, 0, 0]);
+        let today = counter.now().unwrap().date();
+        assert_eq!(counter.history.records()[0].breakdown.by_day[&today], 2);
+        assert!(counter.events.is_empty());
+        assert_eq!(counter.history.records()[0].end, &next_reset - 3.weeks());
+        assert_eq!(counter.history.records()[2].end, &next_reset - 1.week());
+        assert_eq!(counter.count(), 0);
+        assert_eq!(counter.reset_zoned_date_time, next_reset);
+    }
 
     #[test]
-    fn mean_test() {
-        let mut last_six_values = LastSixValues::new();
-        assert!(last_six_values.get_mean().is_nan());
-        last_six_values.add(3);
-        assert_eq!(last_six_values.get_mean(), 3.0);
-        last_six_values.add(7);
-        assert_eq!(last_six_values.get_mean(), 5.0);
-        last_six_values.add(5);
-        last_six_values.add(5);
-        last_six_values.add(5);
-        last_six_values.add(5);
-        assert_eq!(last_six_values.get_mean(), 5.0);
-        last_six_values.add(33);
-        assert_eq!(last_six_values.get_mean(), 10.0);
+    fn device_count_merge_test() {
+        let mut phone = DeviceCount::new();
+        let mut laptop = DeviceCount::new();
+        phone.increment("phone");
+        phone.increment("phone");
+        laptop.increment("laptop");
+        let mut merged = phone.clone();
+        merged.merge(&laptop);
+        laptop.merge(&phone);
+        assert_eq!(merged, laptop);
+        assert_eq!(merged.total(), 3);
+        // merging again changes nothing
+        merged.merge(&phone);
+        assert_eq!(merged.total(), 3);
+        // a reset wins over increments from the previous epoch
+        phone.reset();
+        laptop.increment("laptop");
+        laptop.merge(&phone);
+        assert_eq!(laptop.total(), 0);
+        laptop.increment("laptop");
+        phone.merge(&laptop);
+        assert_eq!(phone.total(), 1);
+        // removals merge like additions
+        phone.add("phone", 3);
+        phone.subtract("phone", 2);
+        laptop.subtract("laptop", 1);
+        laptop.merge(&phone);
+        phone.merge(&laptop);
+        assert_eq!(phone, laptop);
+        assert_eq!(phone.total(), 1);
+    }
+
+    #[test]
+    fn undo_test() {
+        let mut counter_app = CounterApp::new().unwrap();
+        assert!(!counter_app.undo().unwrap());
+        counter_app.set_max(5).unwrap();
+        counter_app.add(4).unwrap();
+        counter_app.subtract(10).unwrap();
+        assert_eq!(counter_app.counter().count(), 0);
+        counter_app.set_max(7).unwrap();
+        assert!(counter_app.undo().unwrap());
+        assert_eq!(counter_app.counter().max, 5);
+        assert!(counter_app.undo().unwrap());
+        assert_eq!(counter_app.counter().count(), 4);
+        counter_app.reset().unwrap();
+        assert!(counter_app.undo().unwrap());
+        assert_eq!(counter_app.counter().count(), 4);
+        // history can't be brought back so nothing before it can be undone
+        counter_app.reset_stats().unwrap();
+        assert!(!counter_app.undo().unwrap());
+        assert_eq!(counter_app.journal.len(), 9);
+        // the selection follows a rename or an added counter being undone
+        counter_app.add_counter("tea").unwrap();
+        counter_app.rename_counter("tea", "coffee").unwrap();
+        assert!(counter_app.undo().unwrap());
+        assert_eq!(counter_app.selected, "tea");
+        assert!(counter_app.undo().unwrap());
+        assert_eq!(counter_app.selected, DEFAULT_COUNTER_NAME);
+        counter_app.increment().unwrap();
+    }
+
+    #[test]
+    fn event_merge_test() {
+        let mut phone = Counter::new().unwrap();
+        let mut laptop = phone.clone();
+        phone.increment("phone").unwrap();
+        laptop.add("laptop", 2).unwrap();
+        phone.merge(&laptop).unwrap();
+        laptop.merge(&phone).unwrap();
+        assert_eq!(phone.events, laptop.events);
+        assert_eq!(phone.events.len(), 2);
+        phone.merge(&laptop).unwrap();
+        assert_eq!(phone.events.len(), 2);
+        // events from before a reset go everywhere the reset does
+        laptop.reset();
+        phone.increment("phone").unwrap();
+        phone.merge(&laptop).unwrap();
+        assert!(phone.events.is_empty());
+    }
+
+    #[test]
+    fn save_and_load_local_test() {
+        let mut counter_app = CounterApp::new().unwrap();
+        counter_app.connection_type = ConnectionType::Memory(MemoryStorage::new());
+        counter_app.set_path(&std::env::temp_dir());
+        let _ = fs::remove_file(&counter_app.state_file_path);
+        assert!(!counter_app.load_local().unwrap());
+        counter_app.add_counter("coffee").unwrap();
+        counter_app.increment().unwrap();
+        counter_app.save_local().unwrap();
+        let saved_counters = counter_app.counters.clone();
+        counter_app.counters = CounterCollection::new().unwrap();
+        counter_app.selected = DEFAULT_COUNTER_NAME.to_string();
+        counter_app.journal.clear();
+        assert!(counter_app.load_local().unwrap());
+        assert_eq!(counter_app.counters, saved_counters);
+        assert_eq!(counter_app.selected, "coffee");
+        assert_eq!(
+            counter_app.journal,
+            vec![
+                Change::Add("coffee".to_string()),
+                Change::Update {
+                    name: "coffee".to_string(),
+                    operation: Operation::Increment
+                }
+            ]
+        );
+        fs::remove_file(&counter_app.state_file_path).unwrap();
+    }
+
+    #[test]
+    fn load_old_local_test() {
+        let mut counter_app = CounterApp::new().unwrap();
+        let path = env::temp_dir().join("ant_counter_load_old_local_test");
+        fs::create_dir_all(&path).unwrap();
+        counter_app.set_path(&path);
+        let mut counter = Counter::new().unwrap();
+        counter.increment("phone").unwrap();
+        let old_state = serde_json::json!({
+            "counter": counter,
+            "journal": [Operation::Increment, Operation::SetMax(3)],
+        });
+        fs::write(&counter_app.state_file_path, old_state.to_string()).unwrap();
+        assert!(counter_app.load_local().unwrap());
+        assert_eq!(
+            counter_app.counters.get(DEFAULT_COUNTER_NAME),
+            Some(&counter)
+        );
+        assert_eq!(
+            counter_app.journal[1],
+            Change::Update {
+                name: DEFAULT_COUNTER_NAME.to_string(),
+                operation: Operation::SetMax(3)
+            }
+        );
+        // anything else is kept aside rather than written over
+        fs::write(&counter_app.state_file_path, "{}").unwrap();
+        assert!(counter_app.load_local().is_err());
+        assert!(!counter_app.state_file_path.exists());
+        let unreadable_path = counter_app
+            .state_file_path
+            .with_extension("json.unreadable");
+        assert_eq!(fs::read_to_string(&unreadable_path).unwrap(), "{}");
+        fs::remove_file(&unreadable_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn error_test() {
+        let mut counter_app = CounterApp::new().unwrap();
+        assert!(matches!(
+            counter_app.get_network_counters().await,
+            Err(Error::NotConnected)
+        ));
+        assert!(matches!(
+            counter_app.select_counter("tea"),
+            Err(Error::NoSuchCounter(name)) if name == "tea"
+        ));
+        assert!(matches!(
+            counter_app.remove_counter(DEFAULT_COUNTER_NAME),
+            Err(Error::InvalidChange(_))
+        ));
+        assert!(matches!(
+            counter_app.set_key_from_hex("not hex"),
+            Err(Error::InvalidKey(_))
+        ));
+        counter_app.set_path(&env::temp_dir().join("ant_counter_error_test"));
+        assert!(matches!(
+            counter_app.set_key_from_file(&TestPrompt),
+            Err(Error::NoKeyFile(_))
+        ));
+    }
+
+    // an app on a memory scratchpad holding the given counters, not yet connected
+    async fn offline_app(
+        network: &CounterCollection,
+        test_name: &str,
+    ) -> (CounterApp, MemoryStorage, SecretKey) {
+        let storage = MemoryStorage::new();
+        let key = SecretKey::random();
+        let content = encode_counters(network).unwrap();
+        storage
+            .create(&key, 99, &content, Receipt::new().into())
+            .await
+            .unwrap();
+        let counter_app = memory_app(&storage, &key, test_name);
+        (counter_app, storage, key)
+    }
+
+    // another device on the same memory scratchpad, not yet connected
+    fn memory_app(storage: &MemoryStorage, key: &SecretKey, test_name: &str) -> CounterApp {
+        let mut counter_app = CounterApp::new().unwrap();
+        counter_app.connection_type = ConnectionType::Memory(storage.clone());
+        let path = env::temp_dir().join(test_name);
+        fs::create_dir_all(&path).unwrap();
+        counter_app.set_path(&path);
+        let fast = RetryPolicy {
+            max_attempts: 2,
+            initial_delay_ms: 10,
+            backoff_factor: 1.0,
+            jitter: 0.0,
+            deadline_secs: 1,
+            wait_before_first_attempt: false,
+        };
+        counter_app.retry.local_read = fast.clone();
+        counter_app.retry.local_verify = fast;
+        counter_app.counter_state = CounterState::LocalWithKey(key.clone());
+        counter_app
+    }
+
+    #[tokio::test]
+    async fn reconnect_test() {
+        let base = CounterCollection::new().unwrap();
+        let mut network = base.clone();
+        let increment = Change::Update {
+            name: DEFAULT_COUNTER_NAME.to_string(),
+            operation: Operation::Increment,
+        };
+        network.apply(&increment, "laptop").unwrap();
+        network.apply(&increment, "laptop").unwrap();
+        let (mut counter_app, _, _) = offline_app(&network, "ant_counter_reconnect_test").await;
+        counter_app.counters = base;
+        counter_app.increment().unwrap();
+        counter_app.add_counter("tea").unwrap();
+        counter_app.add(3).unwrap();
+        counter_app.set_max(5).unwrap();
+        let offline_events = counter_app.counters.get("tea").unwrap().events.clone();
+        counter_app.connect(false).await.unwrap();
+        assert_eq!(counter_app.get_counter_state(), "Connected");
+        assert!(counter_app.journal.is_empty());
+        let default = counter_app.counters.get(DEFAULT_COUNTER_NAME).unwrap();
+        assert_eq!(default.count(), 3);
+        assert_eq!(default.events.len(), 3);
+        let tea = counter_app.counters.get("tea").unwrap();
+        assert_eq!((tea.count(), tea.max), (3, 5));
+        // events keep the time they happened, not when they reached antnet
+        assert_eq!(tea.events, offline_events);
+        assert_eq!(
+            counter_app.get_network_counters().await.unwrap(),
+            counter_app.counters
+        );
+    }
+
+    #[tokio::test]
+    async fn offline_rollover_test() {
+        // antnet is still in a period that has since ended
+        let mut counter = Counter::new().unwrap();
+        counter.reset_zoned_date_time = &counter.reset_zoned_date_time - 1.week();
+        let mut network = CounterCollection::from_counter(DEFAULT_COUNTER_NAME, counter.clone());
+        let increment = Change::Update {
+            name: DEFAULT_COUNTER_NAME.to_string(),
+            operation: Operation::Increment,
+        };
+        network.apply(&increment, "laptop").unwrap();
+        let (mut phone, _, _) = offline_app(&network, "ant_counter_offline_rollover_test").await;
+        phone.counters = CounterCollection::from_counter(DEFAULT_COUNTER_NAME, counter);
+        phone.add(3).unwrap();
+        assert!(phone.counters.reset_if_next_period().unwrap());
+        phone.add(2).unwrap();
+        phone.connect(false).await.unwrap();
+        // both devices' counts go to the period that ended, the new one keeps its own
+        assert_eq!(phone.counter().count(), 2);
+        let records = phone.counter().history.records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].total, 4);
+        assert!(!phone.counters.reset_if_next_period().unwrap());
+        assert_eq!(phone.get_network_counters().await.unwrap(), phone.counters);
+    }
+
+    #[tokio::test]
+    async fn replay_twice_test() {
+        let network = CounterCollection::new().unwrap();
+        let (mut counter_app, storage, key) =
+            offline_app(&network, "ant_counter_replay_twice_test").await;
+        counter_app.counters = network;
+        counter_app.increment().unwrap();
+        counter_app.reset().unwrap();
+        counter_app.add_counter("tea").unwrap();
+        counter_app.rename_counter("tea", "coffee").unwrap();
+        counter_app.add(2).unwrap();
+        counter_app.set_max(4).unwrap();
+        // the upload got there but was never confirmed
+        let content = encode_counters(&counter_app.counters).unwrap();
+        storage.update(&key, 99, &content).await.unwrap();
+        let journal = counter_app.journal.clone();
+        let expected = counter_app.counters.clone();
+        counter_app.connect(false).await.unwrap();
+        assert_eq!(counter_app.counters, expected);
+        // and again, as if this upload wasn't confirmed either
+        counter_app.disconnect();
+        counter_app.journal = journal;
+        counter_app.connect(false).await.unwrap();
+        assert_eq!(counter_app.counters, expected);
+        assert!(!counter_app.counters.contains("tea"));
+        assert_eq!(counter_app.counters.get("coffee").unwrap().count(), 2);
+        let default = counter_app.counters.get(DEFAULT_COUNTER_NAME).unwrap();
+        assert_eq!(default.count.epoch(), 1);
+    }
+
+    #[tokio::test]
+    async fn two_device_test() {
+        let network = CounterCollection::new().unwrap();
+        let (mut laptop, storage, key) = offline_app(&network, "ant_counter_laptop_test").await;
+        let mut phone = memory_app(&storage, &key, "ant_counter_phone_test");
+        laptop.connect(true).await.unwrap();
+        phone.connect(true).await.unwrap();
+        laptop.set_max(10).unwrap();
+        laptop.increment().unwrap();
+        laptop.queue_sync().unwrap();
+        laptop.finish_sync().await.unwrap();
+        // the refresh comes back after a change here so isn't taken, the
+        // upload still has to keep what the laptop did
+        phone.refresh().unwrap();
+        phone.increment().unwrap();
+        phone.queue_sync().unwrap();
+        phone.finish_sync().await.unwrap();
+        let network = phone.get_network_counters().await.unwrap();
+        assert_eq!(network.first().max, 10);
+        assert_eq!(network.first().count(), 2);
+        assert_eq!(phone.counters, network);
+        assert!(phone.journal.is_empty());
+    }
+
+    #[tokio::test]
+    async fn battery_saver_test() {
+        let network = CounterCollection::new().unwrap();
+        let (mut counter_app, _, _) = offline_app(&network, "ant_counter_battery_saver_test").await;
+        counter_app.connect(true).await.unwrap();
+        // an upload is read before and after and every check goes to antnet
+        let calls = counter_app.get_network_calls();
+        counter_app.increment().unwrap();
+        counter_app.queue_sync().unwrap();
+        counter_app.finish_sync().await.unwrap();
+        assert!(counter_app.is_connected().await);
+        counter_app.download().await.unwrap();
+        assert_eq!(counter_app.get_network_calls(), calls + 5);
+        // while antnet has answered recently an upload isn't read back
+        counter_app.battery_saver.enabled = true;
+        let calls = counter_app.get_network_calls();
+        counter_app.increment().unwrap();
+        counter_app.queue_sync().unwrap();
+        counter_app.finish_sync().await.unwrap();
+        assert!(counter_app.is_connected().await);
+        counter_app.download().await.unwrap();
+        counter_app.sync_to_antnet().await.unwrap();
+        assert_eq!(counter_app.get_network_calls(), calls + 3);
+        assert!(counter_app.journal.is_empty());
+        assert_eq!(
+            counter_app.get_network_counters().await.unwrap(),
+            counter_app.counters
+        );
+    }
+
+    // answers like someone who types a passphrase and pays, has no wallet key
+    struct TestPrompt;
+
+    impl Prompt for TestPrompt {
+        fn ask_for_passphrase(&self) -> io::Result<String> {
+            Ok("correct horse".to_string())
+        }
+
+        fn ask_for_new_passphrase(&self) -> io::Result<String> {
+            Ok("correct horse".to_string())
+        }
+
+        fn show_recovery_words(&self, _: &recovery::Mnemonic) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn confirm_cost(&self, _: &AttoTokens) -> io::Result<bool> {
+            Ok(true)
+        }
+
+        fn ask_for_wallet_private_key(&self) -> io::Result<String> {
+            Err(io::Error::other("no wallet key"))
+        }
+    }
+
+    #[tokio::test]
+    async fn create_test() {
+        let network = CounterCollection::new().unwrap();
+        let (mut counter_app, _, _) = offline_app(&network, "ant_counter_create_test").await;
+        let _ = fs::remove_file(&counter_app.key_file_path);
+        // a bad max cost leaves a local counter rather than failing
+        counter_app.wallet.max_cost = Some("lots".to_string());
+        counter_app.create("", &TestPrompt).await.unwrap();
+        assert!(matches!(
+            counter_app.counter_state,
+            CounterState::LocalWithKey(_)
+        ));
+        counter_app.wallet.max_cost = None;
+        counter_app.set_key_from_file(&TestPrompt).unwrap();
+        assert!(counter_app.get_key().is_some());
+        fs::remove_file(&counter_app.key_file_path).unwrap();
+        counter_app.create("", &TestPrompt).await.unwrap();
+        assert!(counter_app.is_connected().await);
     }
 
     #[test]
-    fn last_value_test() {
-        let mut last_six_values = LastSixValues::new();
-        assert_eq!(last_six_values.get_last_value(), 0);
-        last_six_values.add(3);
-        assert_eq!(last_six_values.get_last_value(), 3);
-        last_six_values.add(7);
-        assert_eq!(last_six_values.get_last_value(), 7);
-        for _ in 1..6 {
-            last_six_values.add(5);
-        }
-        assert_eq!(last_six_values.get_last_value(), 5);
+    fn wallet_key_test() {
+        let mut counter_app = CounterApp::new().unwrap();
+        assert!(matches!(
+            counter_app.get_wallet_private_key(&TestPrompt),
+            Err(Error::Input(_))
+        ));
+        counter_app.connection_type = ConnectionType::Local;
+        assert_eq!(
+            counter_app.get_wallet_private_key(&TestPrompt).unwrap(),
+            counter_app.wallet.local_private_key
+        );
+        let path = env::temp_dir().join("ant_counter_wallet_key_test");
+        fs::write(&path, "0xfile\n").unwrap();
+        counter_app.wallet.private_key_file = Some(path.clone());
+        assert_eq!(
+            counter_app.get_wallet_private_key(&TestPrompt).unwrap(),
+            "0xfile"
+        );
+        env::set_var(WALLET_KEY_ENV_VAR, "0xenv");
+        assert_eq!(
+            counter_app.get_wallet_private_key(&TestPrompt).unwrap(),
+            "0xenv"
+        );
+        env::remove_var(WALLET_KEY_ENV_VAR);
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -0,0 +1,83 @@
+use jiff::civil::Date;
+use jiff::Zoned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// one change to the count, kept until the period it was made in finishes
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct Event {
+    pub time: Zoned,
+    pub device_id: String,
+    pub epoch: u64, // of the count it was made to, so a reset drops it on every device
+    pub change: isize,
+}
+
+// net change per day and per hour of the day, what is kept of the events
+// once their period is archived
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+pub struct Breakdown {
+    pub by_day: BTreeMap<Date, isize>,
+    pub by_hour: [isize; 24],
+}
+
+impl Breakdown {
+    // days and hours are taken in the given zone, whichever zone a device was in
+    pub fn from_events(events: &[Event], time_zone: &str) -> Result<Breakdown, jiff::Error> {
+        let mut breakdown = Breakdown::default();
+        for event in events {
+            let time = event.time.in_tz(time_zone)?;
+            *breakdown.by_day.entry(time.date()).or_insert(0) += event.change;
+            breakdown.by_hour[time.hour() as usize] += event.change;
+        }
+        Ok(breakdown)
+    }
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.by_day.is_empty() {
+            return write!(f, "Nothing counted");
+        }
+        writeln!(f, "By day:")?;
+        for (day, change) in &self.by_day {
+            writeln!(f, "  {}: {change}", day.strftime("%a %Y-%m-%d"))?;
+        }
+        write!(f, "By hour:")?;
+        for (hour, change) in self.by_hour.iter().enumerate() {
+            if *change != 0 {
+                write!(f, "\n  {hour:02}:00: {change}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::civil::date;
+
+    #[test]
+    fn breakdown_test() {
+        let event = |time: &str, change: isize| Event {
+            time: time.parse().unwrap(),
+            device_id: "phone".to_string(),
+            epoch: 0,
+            change,
+        };
+        let events = vec![
+            event("2025-03-12T08:15[Europe/London]", 1),
+            event("2025-03-12T08:45[Europe/London]", 2),
+            event("2025-03-12T21:00[Europe/London]", -1),
+            // 01:30 on the 13th in London
+            event("2025-03-12T21:30[America/New_York]", 1),
+        ];
+        let breakdown = Breakdown::from_events(&events, "Europe/London").unwrap();
+        assert_eq!(breakdown.by_day[&date(2025, 3, 12)], 2);
+        assert_eq!(breakdown.by_day[&date(2025, 3, 13)], 1);
+        assert_eq!(breakdown.by_hour[8], 3);
+        assert_eq!(breakdown.by_hour[21], -1);
+        assert_eq!(breakdown.by_hour[1], 1);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::counter::DeviceCount;
+use crate::events::Breakdown;
+use jiff::Zoned;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_RETENTION: usize = 6;
+
+// one finished period, with the max that applied while it ran. the count
+// per device is kept so copies that each closed the period can be merged
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct PeriodRecord {
+    pub start: Zoned,
+    pub end: Zoned,
+    pub total: usize,
+    pub max: usize,
+    #[serde(default)]
+    pub breakdown: Breakdown,
+    #[serde(default)]
+    pub count: DeviceCount,
+}
+
+impl PeriodRecord {
+    fn is_same_period(&self, other: &PeriodRecord) -> bool {
+        self.start.timestamp() == other.start.timestamp()
+            && self.end.timestamp() == other.end.timestamp()
+    }
+
+    // events shared by both copies can't be told apart once added up, so the
+    // breakdown of the fuller record is kept
+    fn merge(&mut self, other: &PeriodRecord) {
+        if other.total > self.total {
+            self.breakdown = other.breakdown.clone();
+        }
+        self.count.merge(&other.count);
+        self.total = self.total.max(other.total).max(self.count.total());
+    }
+}
+
+// finished periods oldest first, only the last `retention` are kept
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
+pub struct PeriodHistory {
+    records: Vec<PeriodRecord>,
+    retention: usize,
+    #[serde(default)]
+    cleared: u64, // times the records were cleared, like a count's epoch
+}
+
+impl Default for PeriodHistory {
+    fn default() -> PeriodHistory {
+        PeriodHistory::new(DEFAULT_RETENTION)
+    }
+}
+
+impl PeriodHistory {
+    pub fn new(retention: usize) -> PeriodHistory {
+        PeriodHistory {
+            records: Vec::new(),
+            retention: retention.max(1),
+            cleared: 0,
+        }
+    }
+
+    pub fn add(&mut self, record: PeriodRecord) {
+        self.records.push(record);
+        self.trim();
+    }
+
+    pub fn records(&self) -> &[PeriodRecord] {
+        &self.records
+    }
+
+    pub fn retention(&self) -> usize {
+        self.retention
+    }
+
+    // dropping retention throws away the oldest records straight away
+    pub fn set_retention(&mut self, retention: usize) {
+        self.retention = retention.max(1);
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.cleared += 1;
+    }
+
+    // records of the same period are merged and the rest taken in, unless one
+    // copy has been cleared since, which then wins
+    pub fn merge(&mut self, other: &PeriodHistory) {
+        if other.cleared > self.cleared {
+            self.records = other.records.clone();
+            self.cleared = other.cleared;
+        } else if other.cleared == self.cleared {
+            for record in &other.records {
+                match self
+                    .records
+                    .iter_mut()
+                    .find(|own| own.is_same_period(record))
+                {
+                    Some(own) => own.merge(record),
+                    None => self.records.push(record.clone()),
+                }
+            }
+            self.records.sort_by_key(|record| record.end.timestamp());
+        }
+        self.trim();
+    }
+
+    pub fn get_last_total(&self) -> usize {
+        self.records.last().map_or(0, |record| record.total)
+    }
+
+    // None until a period has finished
+    pub fn get_mean(&self) -> Option<f64> {
+        if self.records.is_empty() {
+            return None;
+        }
+        let total = self
+            .records
+            .iter()
+            .map(|record| record.total)
+            .sum::<usize>() as f64;
+        Some(total / self.records.len() as f64)
+    }
+
+    fn trim(&mut self) {
+        if self.records.len() > self.retention {
+            let excess = self.records.len() - self.retention;
+            self.records.drain(..excess);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::ToSpan;
+
+    // a week long record ending `weeks_ago` weeks before now
+    fn record(total: usize, weeks_ago: i64) -> PeriodRecord {
+        let end = &Zoned::now() - weeks_ago.weeks();
+        PeriodRecord {
+            start: &end - 1.week(),
+            end,
+            total,
+            max: 10,
+            breakdown: Breakdown::default(),
+            count: DeviceCount::new(),
+        }
+    }
+
+    #[test]
+    fn mean_test() {
+        let mut history = PeriodHistory::new(6);
+        assert_eq!(history.get_mean(), None);
+        history.add(record(3, 7));
+        assert_eq!(history.get_mean(), Some(3.0));
+        history.add(record(7, 6));
+        assert_eq!(history.get_mean(), Some(5.0));
+        for weeks_ago in (2..6).rev() {
+            history.add(record(5, weeks_ago));
+        }
+        assert_eq!(history.get_mean(), Some(5.0));
+        history.add(record(33, 1));
+        assert_eq!(history.get_mean(), Some(10.0));
+    }
+
+    #[test]
+    fn last_value_test() {
+        let mut history = PeriodHistory::new(6);
+        assert_eq!(history.get_last_total(), 0);
+        history.add(record(3, 7));
+        assert_eq!(history.get_last_total(), 3);
+        history.add(record(7, 6));
+        assert_eq!(history.get_last_total(), 7);
+        for weeks_ago in (1..6).rev() {
+            history.add(record(5, weeks_ago));
+        }
+        assert_eq!(history.get_last_total(), 5);
+    }
+
+    #[test]
+    fn retention_test() {
+        let mut history = PeriodHistory::new(3);
+        for weeks_ago in (1..6).rev() {
+            history.add(record(weeks_ago, weeks_ago as i64));
+        }
+        let totals: Vec<usize> = history.records().iter().map(|r| r.total).collect();
+        assert_eq!(totals, vec![3, 2, 1]);
+        history.set_retention(2);
+        assert_eq!(history.records().len(), 2);
+        assert_eq!(history.records()[0].total, 2);
+        history.set_retention(10);
+        history.add(record(0, 0));
+        assert_eq!(history.records().len(), 3);
+    }
+}
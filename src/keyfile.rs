@@ -0,0 +1,90 @@
+use argon2::Argon2;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use eyre::{eyre, Result};
+
+// encrypted key files start with these, anything else is read as the plain
+// hex key files written before encryption
+const MAGIC: [u8; 4] = *b"ANTK";
+const KEY_FILE_VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + SALT_LENGTH + NONCE_LENGTH;
+
+// the cipher can't tell a wrong passphrase from a damaged file
+#[derive(Debug, thiserror::Error)]
+#[error("Wrong passphrase or damaged key file")]
+pub struct WrongPassphrase;
+
+pub fn is_encrypted(content: &[u8]) -> bool {
+    content.starts_with(&MAGIC)
+}
+
+// argon2id with its default cost turns the passphrase into the cipher key,
+// a fresh salt and nonce are used every time
+pub fn encrypt_key(key_hex: &str, passphrase: &str) -> Result<Vec<u8>> {
+    let salt: [u8; SALT_LENGTH] = rand::random();
+    let nonce: [u8; NONCE_LENGTH] = rand::random();
+    let cipher = get_cipher(passphrase, &salt)?;
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), key_hex.as_bytes())
+        .map_err(|_| eyre!("Could not encrypt key"))?;
+    let mut content = Vec::with_capacity(HEADER_LENGTH + ciphertext.len());
+    content.extend_from_slice(&MAGIC);
+    content.push(KEY_FILE_VERSION);
+    content.extend_from_slice(&salt);
+    content.extend_from_slice(&nonce);
+    content.extend_from_slice(&ciphertext);
+    Ok(content)
+}
+
+// returns the hex key, failing with WrongPassphrase if it can't be decrypted
+pub fn decrypt_key(content: &[u8], passphrase: &str) -> Result<String> {
+    if !is_encrypted(content) || content.len() < HEADER_LENGTH {
+        return Err(eyre!("Not an encrypted key file"));
+    }
+    let version = content[MAGIC.len()];
+    if version != KEY_FILE_VERSION {
+        return Err(eyre!(
+            "Key file version {version} is not supported by this app, please update"
+        ));
+    }
+    let (salt, rest) = content[MAGIC.len() + 1..].split_at(SALT_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+    let cipher = get_cipher(passphrase, salt)?;
+    let key_hex = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| WrongPassphrase)?;
+    Ok(String::from_utf8(key_hex)?)
+}
+
+fn get_cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut cipher_key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut cipher_key)
+        .map_err(|error| eyre!("Could not derive key from passphrase: {error}"))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&cipher_key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt_test() {
+        let key_hex = "3b1c9b1a2e4f";
+        let content = encrypt_key(key_hex, "correct horse").unwrap();
+        assert!(is_encrypted(&content));
+        assert!(!content
+            .windows(key_hex.len())
+            .any(|w| w == key_hex.as_bytes()));
+        assert_eq!(decrypt_key(&content, "correct horse").unwrap(), key_hex);
+        assert!(decrypt_key(&content, "wrong horse")
+            .unwrap_err()
+            .is::<WrongPassphrase>());
+        // salt and nonce are fresh each time
+        assert_ne!(content, encrypt_key(key_hex, "correct horse").unwrap());
+        assert!(!is_encrypted(key_hex.as_bytes()));
+        assert!(decrypt_key(&content[..HEADER_LENGTH - 1], "correct horse").is_err());
+    }
+}
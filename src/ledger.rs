@@ -0,0 +1,124 @@
+use crate::message;
+use autonomi::AttoTokens;
+use eyre::{eyre, Result};
+use jiff::Zoned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+// kept next to the key files, one json entry per line so it is only ever
+// appended to and a damaged line doesn't lose the rest
+pub const LEDGER_FILE_NAME: &str = "ledger.jsonl";
+
+// one payment, the cost is kept in atto as a decimal string as the token
+// display format loses or shifts fractions when read back
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct LedgerEntry {
+    pub operation: String,
+    pub network: String,
+    pub cost: String,
+    pub address: String,
+    pub time: Zoned,
+}
+
+impl LedgerEntry {
+    pub fn new(operation: &str, network: &str, cost: &AttoTokens, address: &str) -> LedgerEntry {
+        LedgerEntry {
+            operation: operation.to_string(),
+            network: network.to_string(),
+            cost: cost.as_atto().to_string(),
+            address: address.to_string(),
+            time: Zoned::now(),
+        }
+    }
+
+    pub fn get_cost(&self) -> Result<AttoTokens> {
+        let atto = self
+            .cost
+            .parse()
+            .map_err(|_| eyre!("Not an amount of atto: {}", self.cost))?;
+        Ok(AttoTokens::from_atto(atto))
+    }
+}
+
+pub fn append(path: &Path, entry: &LedgerEntry) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    Ok(())
+}
+
+// no ledger yet means nothing has been paid
+pub fn load(path: &Path) -> Result<Vec<LedgerEntry>> {
+    let Ok(ledger) = fs::read_to_string(path) else {
+        return Ok(Vec::new());
+    };
+    let mut entries = Vec::new();
+    for line in ledger.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(error) => message!("Skipping unreadable ledger entry: {error}"),
+        }
+    }
+    Ok(entries)
+}
+
+pub fn total_by_network(entries: &[LedgerEntry]) -> Result<BTreeMap<String, AttoTokens>> {
+    let mut totals = BTreeMap::new();
+    for entry in entries {
+        let cost = entry.get_cost()?;
+        let total = totals
+            .entry(entry.network.clone())
+            .or_insert(AttoTokens::zero());
+        *total = total
+            .checked_add(cost)
+            .ok_or_else(|| eyre!("Total spend on {} is too large", entry.network))?;
+    }
+    Ok(totals)
+}
+
+// an amount in tokens, as given in the config
+pub fn parse_cost(cost: &str) -> Result<AttoTokens> {
+    AttoTokens::from_str(cost).map_err(|_| eyre!("Not an amount of tokens: {cost}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ledger_test() {
+        let path = std::env::temp_dir().join("ant_counter_ledger_test.jsonl");
+        let _ = fs::remove_file(&path);
+        assert!(load(&path).unwrap().is_empty());
+        let five = parse_cost("5").unwrap();
+        let seven = parse_cost("7").unwrap();
+        append(&path, &LedgerEntry::new("create", "antnet", &five, "a1")).unwrap();
+        append(&path, &LedgerEntry::new("create", "local", &five, "b2")).unwrap();
+        append(&path, &LedgerEntry::new("create", "antnet", &seven, "c3")).unwrap();
+        let entries = load(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].address, "c3");
+        let totals = total_by_network(&entries).unwrap();
+        assert_eq!(
+            totals["antnet"].as_atto(),
+            parse_cost("12").unwrap().as_atto()
+        );
+        assert_eq!(totals["local"].as_atto(), five.as_atto());
+        assert!(parse_cost("lots").is_err());
+        // fractions of a token read back exactly
+        let fraction = AttoTokens::from_u64(1_500_000_000_000_000);
+        let entry = LedgerEntry::new("create", "antnet", &fraction, "d4");
+        append(&path, &entry).unwrap();
+        let entries = load(&path).unwrap();
+        assert_eq!(entries[3].get_cost().unwrap().as_atto(), fraction.as_atto());
+        let totals = total_by_network(&entries).unwrap();
+        assert_eq!(
+            totals["antnet"].as_atto(),
+            parse_cost("12").unwrap().as_atto() + fraction.as_atto()
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
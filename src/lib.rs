@@ -0,0 +1,34 @@
+// counters kept on an antnet scratchpad, the binary is one front end over
+// this and other apps can depend on it the same way
+mod collection;
+mod config;
+mod counter;
+mod events;
+mod history;
+mod keyfile;
+pub mod ledger;
+pub mod messages;
+mod payload;
+mod period;
+mod prompt;
+pub mod recovery;
+mod retry;
+mod stats;
+mod storage;
+mod sync_task;
+
+pub use collection::{Change, CounterCollection, DEFAULT_COUNTER_NAME};
+pub use config::{
+    BatterySaverConfig, Config, Network, RetryConfig, WalletConfig, WALLET_KEY_ENV_VAR,
+};
+pub use counter::{
+    ConnectionType, Counter, CounterApp, CounterState, DeviceCount, Error, Operation,
+};
+pub use events::{Breakdown, Event};
+pub use history::{PeriodHistory, PeriodRecord};
+pub use period::Period;
+pub use prompt::Prompt;
+pub use retry::RetryPolicy;
+pub use stats::{Statistics, Trend};
+pub use storage::{MemoryStorage, StorageBackend};
+pub use sync_task::SyncResult;
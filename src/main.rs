@@ -1,23 +1,38 @@
-use counter::{ConnectionType, CounterApp, CounterState};
+use ant_counter::{
+    Config, ConnectionType, CounterApp, CounterState, Error, MemoryStorage, Period, Statistics,
+    SyncResult,
+};
+use clap::Parser;
+use cli::Cli;
 use eyre::Result;
+use jiff::civil::{Date, Weekday};
 use std::io::{self};
-use std::path::Path;
+use terminal::TerminalPrompt;
 
-mod counter;
+mod cli;
+mod terminal;
+mod tui;
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    run().await?;
+    let cli = Cli::parse();
+    let mut config = Config::load(cli.config.as_deref())?;
+    cli.override_config(&mut config);
+    match &cli.command {
+        Some(command) => cli::run_command(&cli, &config, command).await?,
+        None => run(&cli, &config).await?,
+    }
     Ok(())
 }
 
-async fn run() -> Result<()> {
-    let path = Path::new(""); // diretory path, file name givn in counter
-                              // create app
+async fn run(cli: &Cli, config: &Config) -> Result<()> {
+    let path = &config.key_dir; // diretory path, file name givn in counter
+                                // create app
     let mut counter_app = CounterApp::new()?;
-    // get what type of connection to use
-    loop {
-        println!("Enter (a) to connect to antnet or (l) for a local network or (q) to quit:");
+    counter_app.apply_config(config);
+    // get what type of connection to use, unless given on the command line or config
+    while config.network.is_none() {
+        println!("Enter (a) to connect to antnet, (l) for a local network, (m) for an in memory demo or (q) to quit:");
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
         let input = input.trim();
@@ -30,6 +45,10 @@ async fn run() -> Result<()> {
                 counter_app.connection_type = ConnectionType::Local;
                 break;
             }
+            "m" => {
+                counter_app.connection_type = ConnectionType::Memory(MemoryStorage::new());
+                break;
+            }
             "q" => {
                 counter_app.counter_state = CounterState::Quitting;
                 break;
@@ -41,37 +60,66 @@ async fn run() -> Result<()> {
         }
     }
     counter_app.set_path(&path);
+    counter_app.set_device_id_from_file()?;
     println!("{}", counter_app.get_counter_state());
     // let use choose to use existing coutner from key file or create a new one
     while let CounterState::Initiating = counter_app.counter_state {
-        println!("Enter (u) to use existing counter, (c) to create a new one or (q) to quit:");
+        println!("Enter (u) to use existing counter, (c) to create a new one, (e) to encrypt an existing key file, (r) to recover a counter from its recovery words or (q) to quit:");
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
         let input = input.trim();
         match input {
             "u" => {
-                if let Ok(_) = counter_app.set_key_from_file() {
-                    counter_app.connect(true).await?;
-                } else {
+                if let Err(error) = counter_app.set_key_from_file(&TerminalPrompt) {
                     println!(
-                        "Failed to load key from path: {:?}",
+                        "Failed to load key from path: {:?}, {error}",
                         &counter_app.key_file_path
                     );
                     continue;
                 }
+                if let Err(error) = counter_app.load_local() {
+                    println!("Could not read local counter: {error}");
+                }
+                match counter_app.connect(true).await {
+                    Err(error @ Error::ClientInit(_)) => {
+                        println!("{error}...using local counter")
+                    }
+                    result => result?,
+                }
             }
             "c" => {
-                if let ConnectionType::Antnet = counter_app.connection_type {
-                    println!("Please enter private key:");
-                    let mut input = String::new();
-                    io::stdin().read_line(&mut input)?;
-                    let private_key = input.trim();
-                    counter_app.create(&private_key).await?
-                } else {
-                    let private_key =
-                        "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
-                    counter_app.create(&private_key).await?;
+                let private_key = counter_app.get_wallet_private_key(&TerminalPrompt)?;
+                match counter_app.create(&private_key, &TerminalPrompt).await {
+                    Err(error @ (Error::ClientInit(_) | Error::Payment(_))) => {
+                        println!("{error}...using local counter")
+                    }
+                    result => result?,
+                }
+            }
+            "r" => {
+                let mnemonic = match terminal::ask_for_mnemonic() {
+                    Ok(mnemonic) => mnemonic,
+                    Err(error) => {
+                        println!("Not valid recovery words: {error}");
+                        continue;
+                    }
+                };
+                match counter_app.recover(&mnemonic, &TerminalPrompt).await {
+                    Err(error @ Error::ClientInit(_)) => {
+                        println!("{error}...using local counter")
+                    }
+                    Err(error) => {
+                        println!("Could not recover counter: {error}");
+                        continue;
+                    }
+                    Ok(()) => (),
+                }
+            }
+            "e" => {
+                if let Err(error) = counter_app.encrypt_key_file(&TerminalPrompt) {
+                    println!("Could not encrypt key file: {error}");
                 }
+                continue;
             }
             "q" => counter_app.counter_state = CounterState::Quitting,
             _ => {
@@ -83,45 +131,85 @@ async fn run() -> Result<()> {
     }
 
     if !(CounterState::Quitting == counter_app.counter_state) {
-        println!("{}", counter_app.counter);
-        if counter_app.is_connected().await {
-            counter_app.download().await?;
-            counter_app.print_scratchpad()?;
+        if let Some(name) = &cli.counter {
+            if let Err(error) = counter_app.select_counter(name) {
+                println!("{error}");
+            }
         }
-        match counter_app.counter.reset_if_next_period()? {
+        println!("{}: {}", counter_app.selected, counter_app.counter());
+        // connecting has just read the scratchpad
+        counter_app.print_scratchpad()?;
+        match counter_app.counters.reset_if_next_period()? {
             true => {
+                counter_app.save_local()?;
                 if counter_app.is_connected().await {
                     counter_app.upload().await?;
                 }
-                println!("{}", counter_app.counter);
+                println!("{}: {}", counter_app.selected, counter_app.counter());
             }
             _ => (),
         }
 
         // loop asking user for value to store and then storing on scratch pad
         loop {
+            // uploads happen in the background, say how any finished
+            while let Some(result) = counter_app.poll_sync()? {
+                match result {
+                    SyncResult::Synced { .. } => println!("Synced to antnet"),
+                    SyncResult::Refreshed(_) => (),
+                    SyncResult::Failed(error) => {
+                        println!("Sync failed: {error}...using local counter")
+                    }
+                }
+            }
+            println!("{}: {}", counter_app.selected, counter_app.counter());
             println!("{}", counter_app.get_counter_state());
+            // other devices' changes come in while waiting for input
+            counter_app.refresh()?;
             // get input from user
-            println!("Enter (i) to increment counter, (r) to reset, (rs) to reset statistics, (m) to set max, (d) to disconnect (testing), c to connect (testing) or q to quit:");
+            println!("Enter (i) to increment counter, (+) to add a number, (-) to subtract a number, (u) to undo, (r) to reset, (rs) to reset statistics, (m) to set max, (p) to set period, (z) to set time zone, (ws) to set week start, (h) to show history, (hl) to set history length, (s) to show statistics, (b) to show when things were counted, (l) to list counters, (a) to add a counter, (sc) to select a counter, (rn) to rename a counter, (rm) to remove a counter, (d) to disconnect (testing), c to connect (testing) or q to quit:");
             let mut input = String::new();
             io::stdin().read_line(&mut input)?;
             let input = input.trim();
-            // if connected get counter from antnet
-            if counter_app.get_counter_state() == "Connected" {
-                counter_app.download().await?;
-            }
             match input {
                 "i" => {
-                    counter_app.increment();
-                    counter_app.sync_to_antnet().await?;
+                    counter_app.increment()?;
+                    counter_app.queue_sync()?;
+                }
+                "+" | "-" => {
+                    println!(
+                        "Enter the number to {}: ",
+                        if input == "+" { "add" } else { "subtract" }
+                    );
+                    let mut number = String::new();
+                    io::stdin().read_line(&mut number)?;
+                    let number: usize = match number.trim().parse() {
+                        Ok(number) => number,
+                        Err(_) => {
+                            println!("Must be a positive whole number");
+                            continue;
+                        }
+                    };
+                    match input {
+                        "+" => counter_app.add(number)?,
+                        _ => counter_app.subtract(number)?,
+                    }
+                    counter_app.queue_sync()?;
+                }
+                "u" => {
+                    if !counter_app.undo()? {
+                        println!("Nothing to undo");
+                        continue;
+                    }
+                    counter_app.queue_sync()?;
                 }
                 "r" => {
-                    counter_app.reset();
-                    counter_app.sync_to_antnet().await?;
+                    counter_app.reset()?;
+                    counter_app.queue_sync()?;
                 }
                 "rs" => {
-                    counter_app.counter.reset_stats();
-                    counter_app.sync_to_antnet().await?;
+                    counter_app.reset_stats()?;
+                    counter_app.queue_sync()?;
                 }
                 "m" => {
                     println!("Enter the max for a period: ");
@@ -134,22 +222,173 @@ async fn run() -> Result<()> {
                             continue;
                         }
                     };
-                    counter_app.counter.set_max(input);
-                    counter_app.sync_to_antnet().await?;
+                    counter_app.set_max(input)?;
+                    counter_app.queue_sync()?;
+                }
+                "p" => {
+                    let Some(period) = ask_for_period()? else {
+                        println!("Period not changed");
+                        continue;
+                    };
+                    if let Err(error) = counter_app.set_period(period) {
+                        println!("Period not changed: {error}");
+                        continue;
+                    }
+                    counter_app.queue_sync()?;
+                }
+                "z" => {
+                    println!("Enter the time zone resets happen in (e.g. Europe/London): ");
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    if counter_app.set_time_zone(input.trim()).is_err() {
+                        println!("Unrecognised time zone");
+                        continue;
+                    }
+                    counter_app.queue_sync()?;
+                }
+                "ws" => {
+                    println!(
+                        "Enter the day weeks start on (mon, tue, wed, thu, fri, sat or sun): "
+                    );
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    let Some(week_start) = parse_weekday(input.trim()) else {
+                        println!("Unrecognised day");
+                        continue;
+                    };
+                    counter_app.set_week_start(week_start)?;
+                    counter_app.queue_sync()?;
+                }
+                "h" => {
+                    let history = &counter_app.counter().history;
+                    println!("Keeping the last {} periods:", history.retention());
+                    for record in history.records() {
+                        println!(
+                            "{} to {}: {} of {}",
+                            record.start.date(),
+                            record.end.date(),
+                            record.total,
+                            record.max
+                        );
+                    }
+                    continue;
+                }
+                "s" => {
+                    match Statistics::from_history(&counter_app.counter().history) {
+                        Some(statistics) => println!("{statistics}"),
+                        None => println!("No periods have finished yet"),
+                    }
+                    continue;
+                }
+                "b" => {
+                    println!("Enter how many periods back to look (0 for the current one): ");
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    let Ok(periods_back) = input.trim().parse::<usize>() else {
+                        println!("Must be a positive whole number");
+                        continue;
+                    };
+                    let records = counter_app.counter().history.records();
+                    if periods_back == 0 {
+                        println!("{}", counter_app.counter().breakdown()?);
+                    } else if let Some(record) = records.iter().rev().nth(periods_back - 1) {
+                        println!("{} to {}:", record.start.date(), record.end.date());
+                        println!("{}", record.breakdown);
+                    } else {
+                        println!("Only {} periods in history", records.len());
+                    }
+                    continue;
+                }
+                "hl" => {
+                    println!("Enter the number of periods to keep in history: ");
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    let input: usize = match input.trim().parse() {
+                        Ok(input) if input > 0 => input,
+                        _ => {
+                            println!("History length must be a whole number above zero");
+                            continue;
+                        }
+                    };
+                    counter_app.set_history_length(input)?;
+                    counter_app.queue_sync()?;
+                }
+                "l" => {
+                    for name in counter_app.counters.names() {
+                        let marker = if *name == counter_app.selected {
+                            "*"
+                        } else {
+                            " "
+                        };
+                        println!(
+                            "{marker} {name}: {}",
+                            counter_app.counters.get(name).unwrap()
+                        );
+                    }
+                    continue;
+                }
+                "a" => {
+                    println!("Enter a name for the new counter: ");
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    if let Err(error) = counter_app.add_counter(input.trim()) {
+                        println!("{error}");
+                        continue;
+                    }
+                    counter_app.queue_sync()?;
+                }
+                "sc" => {
+                    println!("Enter the name of the counter to use: ");
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    if let Err(error) = counter_app.select_counter(input.trim()) {
+                        println!("{error}");
+                        continue;
+                    }
+                }
+                "rn" => {
+                    println!("Enter a new name for {}: ", counter_app.selected);
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    let from = counter_app.selected.clone();
+                    if let Err(error) = counter_app.rename_counter(&from, input.trim()) {
+                        println!("{error}");
+                        continue;
+                    }
+                    counter_app.queue_sync()?;
+                }
+                "rm" => {
+                    println!("Enter the name of the counter to remove: ");
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    if let Err(error) = counter_app.remove_counter(input.trim()) {
+                        println!("{error}");
+                        continue;
+                    }
+                    counter_app.queue_sync()?;
                 }
                 "d" => {
                     counter_app.disconnect();
-                    println!("{}", counter_app.counter);
+                    println!("{}: {}", counter_app.selected, counter_app.counter());
                 }
                 "c" => {
                     // if not connected attempt to connect
                     if counter_app.get_counter_state() != "Connected" {
                         println!("Trying to connect to antnet...");
-                        counter_app.connect(false).await?;
+                        match counter_app.connect(false).await {
+                            Err(error @ Error::ClientInit(_)) => println!("{error}"),
+                            result => result?,
+                        }
                         counter_app.print_scratchpad()?;
                     }
                 }
                 "q" => {
+                    if counter_app.is_syncing() {
+                        println!("Waiting for changes to reach antnet...");
+                    }
+                    if let Some(SyncResult::Failed(error)) = counter_app.finish_sync().await? {
+                        println!("Sync failed: {error}, changes are kept locally");
+                    }
                     counter_app.counter_state = CounterState::Quitting;
                     break;
                 }
@@ -159,9 +398,9 @@ async fn run() -> Result<()> {
                 }
             }
             if !(counter_app.get_counter_state() == "Quitting") {
-                match counter_app.counter.reset_if_next_period()? {
+                match counter_app.counters.reset_if_next_period()? {
                     true => {
-                        counter_app.sync_to_antnet().await?;
+                        counter_app.queue_sync()?;
                     }
                     _ => (),
                 }
@@ -169,6 +408,69 @@ async fn run() -> Result<()> {
         }
     }
     println!("Final counter:");
-    println!("{}", counter_app.counter);
+    println!("{}: {}", counter_app.selected, counter_app.counter());
+    println!(
+        "Network calls this session: {}",
+        counter_app.get_network_calls()
+    );
     Ok(())
 }
+
+// returns None if any of the input was not understood
+// longer periods than anyone would count over, and well within what a date can be moved by
+const MAX_PERIOD_DAYS: i64 = 3660;
+const MAX_PERIOD_MINUTES: i64 = 60 * 24 * 366;
+
+fn ask_for_period() -> Result<Option<Period>> {
+    println!("Enter (d) for daily, (w) weekly, (f) fortnightly, (m) monthly, (n) every n days or (t) every n minutes (testing):");
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let period = match input.trim() {
+        "d" => Period::Daily,
+        "w" => Period::Weekly,
+        "f" => Period::Fortnightly,
+        "m" => Period::Monthly,
+        "n" => {
+            println!("Enter the number of days in a period:");
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            let days: i64 = match input.trim().parse() {
+                Ok(days) if days > 0 && days <= MAX_PERIOD_DAYS => days,
+                _ => return Ok(None),
+            };
+            println!("Enter a date a period starts on (YYYY-MM-DD):");
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            let Ok(anchor) = input.trim().parse::<Date>() else {
+                return Ok(None);
+            };
+            Period::EveryNDays { days, anchor }
+        }
+        "t" => {
+            println!("Enter the number of minutes in a period:");
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            match input.trim().parse() {
+                Ok(minutes) if minutes > 0 && minutes <= MAX_PERIOD_MINUTES => {
+                    Period::Minutes(minutes)
+                }
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(period))
+}
+
+fn parse_weekday(input: &str) -> Option<Weekday> {
+    match input.to_lowercase().as_str() {
+        "mon" => Some(Weekday::Monday),
+        "tue" => Some(Weekday::Tuesday),
+        "wed" => Some(Weekday::Wednesday),
+        "thu" => Some(Weekday::Thursday),
+        "fri" => Some(Weekday::Friday),
+        "sat" => Some(Weekday::Saturday),
+        "sun" => Some(Weekday::Sunday),
+        _ => None,
+    }
+}
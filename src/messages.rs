@@ -0,0 +1,50 @@
+use std::sync::mpsc::Sender;
+use std::sync::{Mutex, PoisonError};
+
+// progress messages go to stdout unless a front end that owns the screen has
+// taken them, e.g. the tui shows them on its status line
+static SINK: Mutex<Option<Sender<String>>> = Mutex::new(None);
+
+// None puts them back on stdout
+pub fn set_sink(sink: Option<Sender<String>>) {
+    *SINK.lock().unwrap_or_else(PoisonError::into_inner) = sink;
+}
+
+pub fn send(message: String) {
+    let sink = SINK.lock().unwrap_or_else(PoisonError::into_inner);
+    let message = match &*sink {
+        Some(sink) => match sink.send(message) {
+            Ok(()) => return,
+            Err(error) => error.0, // the front end has gone
+        },
+        None => message,
+    };
+    println!("{message}");
+}
+
+// println! for everything in the library that isn't a prompt
+#[macro_export]
+macro_rules! message {
+    ($($arg:tt)*) => {
+        $crate::messages::send(format!($($arg)*))
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn sink_test() {
+        let (sink, messages) = mpsc::channel();
+        set_sink(Some(sink));
+        crate::message!("Counting {}", 3);
+        set_sink(None);
+        crate::message!("Counting {}", 4);
+        // other tests may be sending at the same time
+        let received: Vec<String> = messages.try_iter().collect();
+        assert!(received.contains(&"Counting 3".to_string()));
+        assert!(!received.contains(&"Counting 4".to_string()));
+    }
+}
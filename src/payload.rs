@@ -0,0 +1,514 @@
+use crate::collection::{CounterCollection, DEFAULT_COUNTER_NAME};
+use crate::counter::{Counter, DeviceCount};
+use crate::events::{Breakdown, Event};
+use crate::history::{PeriodHistory, PeriodRecord, DEFAULT_RETENTION};
+use crate::period::{weekday_serde, Period};
+use autonomi::client::scratchpad::Bytes;
+use eyre::{eyre, Result};
+use jiff::civil::Weekday;
+use jiff::Zoned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// scratchpad content is wrapped in an envelope so a newer binary can tell
+// which layout it was written with and migrate it forward
+const MAGIC: [u8; 4] = *b"ANTC";
+pub const FORMAT_VERSION: u32 = 8;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    magic: [u8; 4],
+    format_version: u32,
+    payload: Vec<u8>,
+}
+
+// always written in the current format, so older scratchpads are rewritten
+// on their next upload
+pub fn encode_counters(counters: &CounterCollection) -> Result<Bytes> {
+    let envelope = Envelope {
+        magic: MAGIC,
+        format_version: FORMAT_VERSION,
+        payload: bincode::serialize(counters)?,
+    };
+    Ok(Bytes::from(bincode::serialize(&envelope)?))
+}
+
+pub fn decode_counters(content: &[u8]) -> Result<CounterCollection> {
+    // written before the envelope existed, a bare bincode counter
+    if !content.starts_with(&MAGIC) {
+        return migrate(0, content);
+    }
+    let envelope: Envelope = bincode::deserialize(content)?;
+    migrate(envelope.format_version, &envelope.payload)
+}
+
+// older layouts are converted one version at a time up to the current
+// collection, each read_vn reads anything up to version n
+fn migrate(format_version: u32, payload: &[u8]) -> Result<CounterCollection> {
+    match format_version {
+        0..=7 => Ok(read_v7(format_version, payload)?.into()),
+        FORMAT_VERSION => Ok(bincode::deserialize(payload)?),
+        _ => Err(eyre!(
+            "Counter format version {format_version} is newer than this app supports ({FORMAT_VERSION}), please update"
+        )),
+    }
+}
+
+fn read_v1(format_version: u32, payload: &[u8]) -> Result<CounterV1> {
+    match format_version {
+        0 => Ok(bincode::deserialize::<CounterV0>(payload)?.into()),
+        _ => Ok(bincode::deserialize(payload)?),
+    }
+}
+
+fn read_v2(format_version: u32, payload: &[u8]) -> Result<CounterV2> {
+    match format_version {
+        0..=1 => Ok(read_v1(format_version, payload)?.into()),
+        _ => Ok(bincode::deserialize(payload)?),
+    }
+}
+
+fn read_v3(format_version: u32, payload: &[u8]) -> Result<CounterV3> {
+    match format_version {
+        0..=2 => Ok(read_v2(format_version, payload)?.into()),
+        _ => Ok(bincode::deserialize(payload)?),
+    }
+}
+
+fn read_v4(format_version: u32, payload: &[u8]) -> Result<CounterV4> {
+    match format_version {
+        0..=3 => Ok(read_v3(format_version, payload)?.into()),
+        _ => Ok(bincode::deserialize(payload)?),
+    }
+}
+
+fn read_v5(format_version: u32, payload: &[u8]) -> Result<CollectionV5> {
+    match format_version {
+        // a single counter, which becomes the only one in the collection
+        0..=4 => Ok(CollectionV5 {
+            counters: BTreeMap::from([(
+                DEFAULT_COUNTER_NAME.to_string(),
+                read_v4(format_version, payload)?,
+            )]),
+        }),
+        _ => Ok(bincode::deserialize(payload)?),
+    }
+}
+
+fn read_v6(format_version: u32, payload: &[u8]) -> Result<CollectionV6> {
+    match format_version {
+        0..=5 => Ok(read_v5(format_version, payload)?.into()),
+        _ => Ok(bincode::deserialize(payload)?),
+    }
+}
+
+fn read_v7(format_version: u32, payload: &[u8]) -> Result<CollectionV7> {
+    match format_version {
+        0..=6 => Ok(read_v6(format_version, payload)?.into()),
+        _ => Ok(bincode::deserialize(payload)?),
+    }
+}
+
+// grow only count per device, with no removals
+#[derive(Serialize, Deserialize)]
+struct DeviceCountV1 {
+    epoch: u64,
+    counts: BTreeMap<String, usize>,
+}
+
+// the last six period totals, most recent last, with no dates
+#[derive(Serialize, Deserialize)]
+struct LastSixValues {
+    values: Vec<usize>,
+}
+
+// single count shared by every device
+#[derive(Serialize, Deserialize)]
+struct CounterV0 {
+    count: usize,
+    max: usize,
+    last_six_values: LastSixValues,
+    reset_zoned_date_time: Zoned,
+}
+
+impl From<CounterV0> for CounterV1 {
+    fn from(counter: CounterV0) -> CounterV1 {
+        CounterV1 {
+            count: DeviceCountV1 {
+                epoch: 0,
+                counts: BTreeMap::from([("migrated".to_string(), counter.count)]),
+            },
+            max: counter.max,
+            last_six_values: counter.last_six_values,
+            reset_zoned_date_time: counter.reset_zoned_date_time,
+        }
+    }
+}
+
+// count per device, always reset weekly
+#[derive(Serialize, Deserialize)]
+struct CounterV1 {
+    count: DeviceCountV1,
+    max: usize,
+    last_six_values: LastSixValues,
+    reset_zoned_date_time: Zoned,
+}
+
+impl From<CounterV1> for CounterV2 {
+    fn from(counter: CounterV1) -> CounterV2 {
+        CounterV2 {
+            count: counter.count,
+            max: counter.max,
+            last_six_values: counter.last_six_values,
+            reset_zoned_date_time: counter.reset_zoned_date_time,
+            period: Period::Weekly,
+        }
+    }
+}
+
+// periods in whatever time zone the device was in, weeks starting on monday
+#[derive(Serialize, Deserialize)]
+struct CounterV2 {
+    count: DeviceCountV1,
+    max: usize,
+    last_six_values: LastSixValues,
+    reset_zoned_date_time: Zoned,
+    period: Period,
+}
+
+impl From<CounterV2> for CounterV3 {
+    fn from(counter: CounterV2) -> CounterV3 {
+        // the zone of the device that last reset the counter is the best guess of home
+        let time_zone = counter
+            .reset_zoned_date_time
+            .time_zone()
+            .iana_name()
+            .unwrap_or("UTC")
+            .to_string();
+        CounterV3 {
+            count: counter.count,
+            max: counter.max,
+            last_six_values: counter.last_six_values,
+            reset_zoned_date_time: counter.reset_zoned_date_time,
+            period: counter.period,
+            time_zone,
+            week_start: Weekday::Monday,
+        }
+    }
+}
+
+// home time zone and week start, history without dates
+#[derive(Serialize, Deserialize)]
+struct CounterV3 {
+    count: DeviceCountV1,
+    max: usize,
+    last_six_values: LastSixValues,
+    reset_zoned_date_time: Zoned,
+    period: Period,
+    time_zone: String,
+    #[serde(with = "weekday_serde")]
+    week_start: Weekday,
+}
+
+impl From<CounterV3> for CounterV4 {
+    fn from(counter: CounterV3) -> CounterV4 {
+        // the values were back to back periods ending where the current one
+        // started, the max in force then is not known so the current one is
+        // used, values too far back to date are dropped
+        let mut records = Vec::new();
+        let mut end = counter
+            .period
+            .previous_start(&counter.reset_zoned_date_time);
+        for total in counter.last_six_values.values.into_iter().rev() {
+            let Ok(period_end) = end else { break };
+            let Ok(start) = counter.period.previous_start(&period_end) else {
+                break;
+            };
+            records.push(PeriodRecordV1 {
+                start: start.clone(),
+                end: period_end,
+                total,
+                max: counter.max,
+            });
+            end = Ok(start);
+        }
+        records.reverse();
+        let history = PeriodHistoryV1 {
+            records,
+            retention: DEFAULT_RETENTION,
+        };
+        CounterV4 {
+            count: counter.count,
+            max: counter.max,
+            history,
+            reset_zoned_date_time: counter.reset_zoned_date_time,
+            period: counter.period,
+            time_zone: counter.time_zone,
+            week_start: counter.week_start,
+        }
+    }
+}
+
+// a finished period without a breakdown of when things were counted
+#[derive(Serialize, Deserialize)]
+struct PeriodRecordV1 {
+    start: Zoned,
+    end: Zoned,
+    total: usize,
+    max: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PeriodHistoryV1 {
+    records: Vec<PeriodRecordV1>,
+    retention: usize,
+}
+
+impl From<PeriodHistoryV1> for PeriodHistoryV7 {
+    fn from(history: PeriodHistoryV1) -> PeriodHistoryV7 {
+        let records = history
+            .records
+            .into_iter()
+            .map(|record| PeriodRecordV7 {
+                start: record.start,
+                end: record.end,
+                total: record.total,
+                max: record.max,
+                breakdown: Breakdown::default(),
+            })
+            .collect();
+        PeriodHistoryV7 {
+            records,
+            retention: history.retention,
+        }
+    }
+}
+
+// dated history, counts could only go up
+#[derive(Serialize, Deserialize)]
+struct CounterV4 {
+    count: DeviceCountV1,
+    max: usize,
+    history: PeriodHistoryV1,
+    reset_zoned_date_time: Zoned,
+    period: Period,
+    time_zone: String,
+    #[serde(with = "weekday_serde")]
+    week_start: Weekday,
+}
+
+impl From<CounterV4> for CounterV6 {
+    fn from(counter: CounterV4) -> CounterV6 {
+        CounterV6 {
+            count: DeviceCount::from_counts(counter.count.epoch, counter.count.counts),
+            max: counter.max,
+            history: counter.history,
+            reset_zoned_date_time: counter.reset_zoned_date_time,
+            period: counter.period,
+            time_zone: counter.time_zone,
+            week_start: counter.week_start,
+        }
+    }
+}
+
+// several named counters, counts could only go up
+#[derive(Serialize, Deserialize)]
+struct CollectionV5 {
+    counters: BTreeMap<String, CounterV4>,
+}
+
+impl From<CollectionV5> for CollectionV6 {
+    fn from(collection: CollectionV5) -> CollectionV6 {
+        CollectionV6 {
+            counters: collection
+                .counters
+                .into_iter()
+                .map(|(name, counter)| (name, counter.into()))
+                .collect(),
+        }
+    }
+}
+
+// counts can go down, no record of when anything was counted
+#[derive(Serialize, Deserialize)]
+struct CounterV6 {
+    count: DeviceCount,
+    max: usize,
+    history: PeriodHistoryV1,
+    reset_zoned_date_time: Zoned,
+    period: Period,
+    time_zone: String,
+    #[serde(with = "weekday_serde")]
+    week_start: Weekday,
+}
+
+impl From<CounterV6> for CounterV7 {
+    fn from(counter: CounterV6) -> CounterV7 {
+        CounterV7 {
+            count: counter.count,
+            max: counter.max,
+            history: counter.history.into(),
+            reset_zoned_date_time: counter.reset_zoned_date_time,
+            period: counter.period,
+            time_zone: counter.time_zone,
+            week_start: counter.week_start,
+            events: Vec::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CollectionV6 {
+    counters: BTreeMap<String, CounterV6>,
+}
+
+impl From<CollectionV6> for CollectionV7 {
+    fn from(collection: CollectionV6) -> CollectionV7 {
+        CollectionV7 {
+            counters: collection
+                .counters
+                .into_iter()
+                .map(|(name, counter)| (name, counter.into()))
+                .collect(),
+        }
+    }
+}
+
+// a finished period without the count per device
+#[derive(Serialize, Deserialize)]
+struct PeriodRecordV7 {
+    start: Zoned,
+    end: Zoned,
+    total: usize,
+    max: usize,
+    breakdown: Breakdown,
+}
+
+// records could only be cleared, not merged
+#[derive(Serialize, Deserialize)]
+struct PeriodHistoryV7 {
+    records: Vec<PeriodRecordV7>,
+    retention: usize,
+}
+
+impl From<PeriodHistoryV7> for PeriodHistory {
+    fn from(history: PeriodHistoryV7) -> PeriodHistory {
+        let mut migrated_history = PeriodHistory::new(history.retention);
+        for record in history.records {
+            migrated_history.add(PeriodRecord {
+                start: record.start,
+                end: record.end,
+                total: record.total,
+                max: record.max,
+                breakdown: record.breakdown,
+                count: DeviceCount::new(),
+            });
+        }
+        migrated_history
+    }
+}
+
+// a period rollover reset the count into a new epoch
+#[derive(Serialize, Deserialize)]
+struct CounterV7 {
+    count: DeviceCount,
+    max: usize,
+    history: PeriodHistoryV7,
+    reset_zoned_date_time: Zoned,
+    period: Period,
+    time_zone: String,
+    #[serde(with = "weekday_serde")]
+    week_start: Weekday,
+    events: Vec<Event>,
+}
+
+impl From<CounterV7> for Counter {
+    fn from(counter: CounterV7) -> Counter {
+        Counter {
+            count: counter.count,
+            max: counter.max,
+            history: counter.history.into(),
+            reset_zoned_date_time: counter.reset_zoned_date_time,
+            period: counter.period,
+            time_zone: counter.time_zone,
+            week_start: counter.week_start,
+            events: counter.events,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CollectionV7 {
+    counters: BTreeMap<String, CounterV7>,
+}
+
+impl From<CollectionV7> for CounterCollection {
+    fn from(collection: CollectionV7) -> CounterCollection {
+        CounterCollection::from_counters(
+            collection
+                .counters
+                .into_iter()
+                .map(|(name, counter)| (name, counter.into()))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jiff::ToSpan;
+
+    #[test]
+    fn round_trip_test() {
+        let mut counter = Counter::new().unwrap();
+        counter.increment("phone").unwrap();
+        counter.set_max(5);
+        counter.set_period(Period::Daily).unwrap();
+        let mut counters = CounterCollection::from_counter("coffee", counter);
+        counters.add("screen time").unwrap();
+        let content = encode_counters(&counters).unwrap();
+        assert_eq!(&content[..4], &MAGIC);
+        assert_eq!(decode_counters(&content).unwrap(), counters);
+    }
+
+    #[test]
+    fn migrate_v0_test() {
+        let counter_v0 = CounterV0 {
+            count: 3,
+            max: 10,
+            last_six_values: LastSixValues { values: vec![4, 0] },
+            reset_zoned_date_time: "2025-03-17T00:00[Europe/London]".parse().unwrap(),
+        };
+        let counters = decode_counters(&bincode::serialize(&counter_v0).unwrap()).unwrap();
+        let counter = counters.get(DEFAULT_COUNTER_NAME).unwrap();
+        assert_eq!(counter.count(), 3);
+        assert_eq!(counter.max, 10);
+        let records = counter.history.records();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].total, 4);
+        assert_eq!(records[1].total, 0);
+        assert_eq!(
+            records[0].start.to_string(),
+            "2025-02-24T00:00:00+00:00[Europe/London]"
+        );
+        assert_eq!(
+            records[1].end,
+            records[1].start.checked_add(1.week()).unwrap()
+        );
+        assert_eq!(records[0].end, records[1].start);
+        assert_eq!(counter.period, Period::Weekly);
+        assert_eq!(counter.time_zone, "Europe/London");
+        assert_eq!(counter.week_start, Weekday::Monday);
+    }
+
+    #[test]
+    fn newer_version_test() {
+        let envelope = Envelope {
+            magic: MAGIC,
+            format_version: FORMAT_VERSION + 1,
+            payload: Vec::new(),
+        };
+        assert!(decode_counters(&bincode::serialize(&envelope).unwrap()).is_err());
+    }
+}
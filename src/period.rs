@@ -0,0 +1,172 @@
+use jiff::civil::{date, Date, Weekday};
+use jiff::{Span, ToSpan, Zoned};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+// fortnights are counted from a fixed monday, moved on to the week start day,
+// so every device agrees on which week a fortnight starts in
+const FORTNIGHT_ANCHOR: Date = date(2024, 1, 1);
+
+// how often the counter resets, kept with the counter so every device agrees
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone, Default)]
+pub enum Period {
+    Daily,
+    #[default]
+    Weekly,
+    Fortnightly,
+    Monthly,
+    EveryNDays {
+        days: i64,
+        anchor: Date,
+    },
+    Minutes(i64), // for testing
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Period::Daily => write!(f, "daily"),
+            Period::Weekly => write!(f, "weekly"),
+            Period::Fortnightly => write!(f, "fortnightly"),
+            Period::Monthly => write!(f, "monthly"),
+            Period::EveryNDays { days, anchor } => write!(f, "every {days} days from {anchor}"),
+            Period::Minutes(minutes) => write!(f, "every {minutes} minutes"),
+        }
+    }
+}
+
+impl Period {
+    // start of the first period after now, in the time zone of now, fails
+    // rather than panics if a period is too long to add to it
+    pub fn next_start(&self, now: &Zoned, week_start: Weekday) -> Result<Zoned, jiff::Error> {
+        match self {
+            Period::Daily => Ok(&now.start_of_day()? + 1.day()),
+            Period::Weekly => get_start_of_next_week(now, week_start),
+            Period::Fortnightly => {
+                let anchor = FORTNIGHT_ANCHOR + week_start.since(Weekday::Monday).days();
+                get_start_of_next_n_days(now, 14, anchor)
+            }
+            Period::Monthly => Ok(&now.start_of_day()?.first_of_month()? + 1.month()),
+            Period::EveryNDays { days, anchor } => get_start_of_next_n_days(now, *days, *anchor),
+            Period::Minutes(minutes) => now.checked_add(Span::new().try_minutes(*minutes)?),
+        }
+    }
+
+    // start of the period that ends at the given boundary
+    pub fn previous_start(&self, end: &Zoned) -> Result<Zoned, jiff::Error> {
+        match self {
+            Period::Daily => end.checked_sub(1.day()),
+            Period::Weekly => end.checked_sub(1.week()),
+            Period::Fortnightly => end.checked_sub(2.weeks()),
+            Period::Monthly => end.checked_sub(1.month()),
+            Period::EveryNDays { days, .. } => end.checked_sub(Span::new().try_days(*days)?),
+            Period::Minutes(minutes) => end.checked_sub(Span::new().try_minutes(*minutes)?),
+        }
+    }
+}
+
+fn get_start_of_next_week(now: &Zoned, week_start: Weekday) -> Result<Zoned, jiff::Error> {
+    let now = now.start_of_day()?;
+    let days_to_next_week = 7 - now.weekday().since(week_start);
+    Ok(&now + days_to_next_week.days())
+}
+
+// periods of n days run back to back from the anchor date, which can be in the past or future
+fn get_start_of_next_n_days(now: &Zoned, days: i64, anchor: Date) -> Result<Zoned, jiff::Error> {
+    let days_since_anchor = i64::from(anchor.until(now.date())?.get_days());
+    let periods_since_anchor = days_since_anchor.div_euclid(days);
+    let next_start =
+        anchor.checked_add(Span::new().try_days((periods_since_anchor + 1) * days)?)?;
+    next_start.to_zoned(now.time_zone().clone())
+}
+
+// jiff has no serde support for weekdays so they are stored as days after monday
+pub mod weekday_serde {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(weekday: &Weekday, serializer: S) -> Result<S::Ok, S::Error> {
+        weekday.to_monday_zero_offset().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Weekday, D::Error> {
+        let offset = i8::deserialize(deserializer)?;
+        Weekday::from_monday_zero_offset(offset).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_start_test() {
+        // a wednesday
+        let now: Zoned = "2025-03-12T15:30[Europe/London]".parse().unwrap();
+        let next_start = |period: Period| {
+            period
+                .next_start(&now, Weekday::Monday)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            next_start(Period::Daily),
+            "2025-03-13T00:00:00+00:00[Europe/London]"
+        );
+        assert_eq!(
+            next_start(Period::Weekly),
+            "2025-03-17T00:00:00+00:00[Europe/London]"
+        );
+        assert_eq!(
+            next_start(Period::Fortnightly),
+            "2025-03-24T00:00:00+00:00[Europe/London]"
+        );
+        assert_eq!(
+            next_start(Period::Monthly),
+            "2025-04-01T00:00:00+01:00[Europe/London]"
+        );
+        assert_eq!(
+            next_start(Period::EveryNDays {
+                days: 3,
+                anchor: date(2025, 3, 10)
+            }),
+            "2025-03-13T00:00:00+00:00[Europe/London]"
+        );
+        assert_eq!(
+            next_start(Period::EveryNDays {
+                days: 10,
+                anchor: date(2025, 3, 20)
+            }),
+            "2025-03-20T00:00:00+00:00[Europe/London]"
+        );
+        assert_eq!(
+            next_start(Period::Minutes(10)),
+            "2025-03-12T15:40:00+00:00[Europe/London]"
+        );
+        // weeks starting on a sunday, in another time zone
+        let now = now.in_tz("America/New_York").unwrap();
+        let next_start = |period: Period| {
+            period
+                .next_start(&now, Weekday::Sunday)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            next_start(Period::Weekly),
+            "2025-03-16T00:00:00-04:00[America/New_York]"
+        );
+        assert_eq!(
+            next_start(Period::Fortnightly),
+            "2025-03-16T00:00:00-04:00[America/New_York]"
+        );
+        // too long to add to now is an error, not a panic
+        assert!(Period::Minutes(99999999999)
+            .next_start(&now, Weekday::Monday)
+            .is_err());
+        let every_n_days = Period::EveryNDays {
+            days: 10000000,
+            anchor: date(2025, 3, 10),
+        };
+        assert!(every_n_days.next_start(&now, Weekday::Monday).is_err());
+        assert!(every_n_days.previous_start(&now).is_err());
+    }
+}
@@ -0,0 +1,22 @@
+use crate::recovery::Mnemonic;
+use autonomi::AttoTokens;
+use std::io;
+
+// what CounterApp has to ask the person using it, each front end asks in its
+// own way, e.g. on the terminal or in an app's dialogs
+pub trait Prompt {
+    // to unlock an encrypted key file
+    fn ask_for_passphrase(&self) -> io::Result<String>;
+
+    // to lock a new key file, or one being encrypted
+    fn ask_for_new_passphrase(&self) -> io::Result<String>;
+
+    // returns once the words are written down, they aren't shown again
+    fn show_recovery_words(&self, mnemonic: &Mnemonic) -> io::Result<()>;
+
+    // false to not pay for a new scratchpad
+    fn confirm_cost(&self, cost: &AttoTokens) -> io::Result<bool>;
+
+    // only asked for if no environment variable, file or local network has it
+    fn ask_for_wallet_private_key(&self) -> io::Result<String>;
+}
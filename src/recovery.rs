@@ -0,0 +1,43 @@
+use autonomi::SecretKey;
+pub use bip39::Mnemonic;
+use eyre::Result;
+
+// 24 words from 256 bits of randomness
+pub fn generate_mnemonic() -> Result<Mnemonic> {
+    Ok(Mnemonic::from_entropy(&rand::random::<[u8; 32]>())?)
+}
+
+// case and extra spaces don't matter, the checksum word catches most typos
+pub fn parse_mnemonic(words: &str) -> Result<Mnemonic> {
+    let words = words.split_whitespace().collect::<Vec<_>>().join(" ");
+    Ok(Mnemonic::parse(words.to_lowercase())?)
+}
+
+// the first 32 bytes of the bip39 seed, read big endian, with the top two bits
+// cleared so it is always below the bls12-381 group order and a valid key
+pub fn key_from_mnemonic(mnemonic: &Mnemonic) -> Result<SecretKey> {
+    let seed = mnemonic.to_seed("");
+    let mut key_bytes = [0u8; 32];
+    key_bytes.copy_from_slice(&seed[..32]);
+    key_bytes[0] &= 0x3f;
+    let key_hex: String = key_bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    Ok(SecretKey::from_hex(&key_hex)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_from_mnemonic_test() {
+        let mnemonic = generate_mnemonic().unwrap();
+        assert_eq!(mnemonic.word_count(), 24);
+        let key = key_from_mnemonic(&mnemonic).unwrap();
+        let typed = format!("  {}\n", mnemonic.to_string().to_uppercase());
+        let recovered = key_from_mnemonic(&parse_mnemonic(&typed).unwrap()).unwrap();
+        assert_eq!(key.to_hex(), recovered.to_hex());
+        let other = key_from_mnemonic(&generate_mnemonic().unwrap()).unwrap();
+        assert_ne!(key.to_hex(), other.to_hex());
+        assert!(parse_mnemonic("abandon abandon abandon").is_err());
+    }
+}
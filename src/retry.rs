@@ -0,0 +1,130 @@
+use crate::message;
+use eyre::{eyre, Result};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use tokio::time::{sleep, Duration, Instant};
+
+// how many times and for how long to keep trying something on the network,
+// every field has to be given when one is set in the config file
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_delay_ms: u64,
+    pub backoff_factor: f64, // each wait is this many times the one before
+    pub jitter: f64,         // waits vary randomly by up to this fraction either way
+    pub deadline_secs: u64,  // no attempt starts after this long
+    pub wait_before_first_attempt: bool, // for checks on a write that has to replicate first
+}
+
+impl RetryPolicy {
+    // the wait before the nth retry, counting from zero
+    pub fn get_delay(&self, retry: u32) -> Duration {
+        let delay = self.initial_delay_ms as f64 * self.backoff_factor.powi(retry as i32);
+        let jitter = 1.0 + self.jitter * (rand::random::<f64>() * 2.0 - 1.0);
+        Duration::from_millis((delay * jitter).max(0.0) as u64)
+    }
+
+    // keeps calling the operation until it succeeds, the attempts run out or
+    // the next wait would end past the deadline, then returns the last error
+    pub async fn retry<T, F, Fut>(&self, description: &str, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let deadline = Instant::now() + Duration::from_secs(self.deadline_secs);
+        let mut last_error = eyre!("{description}: no attempts allowed");
+        for attempt in 1..=self.max_attempts {
+            let retry = match self.wait_before_first_attempt {
+                true => Some(attempt - 1),
+                false => attempt.checked_sub(2),
+            };
+            if let Some(retry) = retry {
+                let delay = self.get_delay(retry);
+                if Instant::now() + delay > deadline {
+                    break;
+                }
+                sleep(delay).await;
+                message!("{description} attempt {attempt}...");
+            }
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(error) => last_error = error,
+            }
+        }
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(max_attempts: u32, deadline_secs: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_delay_ms: 10,
+            backoff_factor: 2.0,
+            jitter: 0.0,
+            deadline_secs,
+            wait_before_first_attempt: false,
+        }
+    }
+
+    #[test]
+    fn delay_test() {
+        let policy = policy(5, 1);
+        assert_eq!(policy.get_delay(0), Duration::from_millis(10));
+        assert_eq!(policy.get_delay(3), Duration::from_millis(80));
+        let jittery = RetryPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        for _ in 0..20 {
+            let delay = jittery.get_delay(1);
+            assert!(delay >= Duration::from_millis(10) && delay <= Duration::from_millis(30));
+        }
+    }
+
+    #[tokio::test]
+    async fn retry_test() {
+        let mut attempts = 0;
+        let result = policy(5, 1)
+            .retry("Counting", || {
+                attempts += 1;
+                let attempt = attempts;
+                async move {
+                    match attempt {
+                        3 => Ok(attempt),
+                        _ => Err(eyre!("not yet")),
+                    }
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 3);
+        // gives up when the attempts run out
+        attempts = 0;
+        let result: Result<()> = policy(2, 1)
+            .retry("Counting", || {
+                attempts += 1;
+                async { Err(eyre!("never")) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 2);
+        // or when the next wait would pass the deadline
+        attempts = 0;
+        let slow = RetryPolicy {
+            initial_delay_ms: 2000,
+            ..policy(5, 1)
+        };
+        let result: Result<()> = slow
+            .retry("Counting", || {
+                attempts += 1;
+                async { Err(eyre!("never")) }
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+}
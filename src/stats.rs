@@ -0,0 +1,185 @@
+use crate::history::PeriodHistory;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Trend {
+    Rising,
+    Falling,
+    Steady,
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trend::Rising => write!(f, "rising"),
+            Trend::Falling => write!(f, "falling"),
+            Trend::Steady => write!(f, "steady"),
+        }
+    }
+}
+
+// summary of the finished periods in a history
+#[derive(Debug, PartialEq, Clone)]
+pub struct Statistics {
+    pub periods: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub min: usize,
+    pub max: usize,
+    pub standard_deviation: f64,
+    pub trend: Trend,
+    pub percent_under_limit: f64, // periods with a total at or under the max in force then
+    pub change_from_previous: Option<isize>, // last period total minus the one before
+    pub percent_change_from_previous: Option<f64>, // None if the one before was zero
+}
+
+impl Statistics {
+    // None until at least one period has finished
+    pub fn from_history(history: &PeriodHistory) -> Option<Statistics> {
+        let records = history.records();
+        if records.is_empty() {
+            return None;
+        }
+        let totals: Vec<usize> = records.iter().map(|record| record.total).collect();
+        let periods = totals.len();
+        let mean = totals.iter().sum::<usize>() as f64 / periods as f64;
+        let mut sorted_totals = totals.clone();
+        sorted_totals.sort_unstable();
+        let median = if periods % 2 == 1 {
+            sorted_totals[periods / 2] as f64
+        } else {
+            (sorted_totals[periods / 2 - 1] + sorted_totals[periods / 2]) as f64 / 2.0
+        };
+        let variance = totals
+            .iter()
+            .map(|total| (*total as f64 - mean).powi(2))
+            .sum::<f64>()
+            / periods as f64;
+        let under_limit = records
+            .iter()
+            .filter(|record| record.total <= record.max)
+            .count();
+        let (change_from_previous, percent_change_from_previous) = match totals[..] {
+            [.., previous, last] => (
+                Some(last as isize - previous as isize),
+                (previous > 0).then(|| (last as f64 - previous as f64) / previous as f64 * 100.0),
+            ),
+            _ => (None, None),
+        };
+        Some(Statistics {
+            periods,
+            mean,
+            median,
+            min: sorted_totals[0],
+            max: sorted_totals[periods - 1],
+            standard_deviation: variance.sqrt(),
+            trend: get_trend(&totals, mean),
+            percent_under_limit: under_limit as f64 / periods as f64 * 100.0,
+            change_from_previous,
+            percent_change_from_previous,
+        })
+    }
+}
+
+impl fmt::Display for Statistics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Periods: {}", self.periods)?;
+        writeln!(f, "Mean: {:.1}, median: {:.1}", self.mean, self.median)?;
+        writeln!(f, "Lowest: {}, highest: {}", self.min, self.max)?;
+        writeln!(f, "Standard deviation: {:.1}", self.standard_deviation)?;
+        writeln!(f, "Trend: {}", self.trend)?;
+        writeln!(
+            f,
+            "Under limit: {:.0}% of periods",
+            self.percent_under_limit
+        )?;
+        match (self.change_from_previous, self.percent_change_from_previous) {
+            (Some(change), Some(percent)) => {
+                write!(
+                    f,
+                    "Change from previous period: {change:+} ({percent:+.0}%)"
+                )
+            }
+            (Some(change), None) => write!(f, "Change from previous period: {change:+}"),
+            _ => write!(f, "Change from previous period: none yet"),
+        }
+    }
+}
+
+// slope of the least squares line through the totals, anything under 5% of
+// the mean per period counts as steady
+fn get_trend(totals: &[usize], mean: f64) -> Trend {
+    if totals.len() < 2 {
+        return Trend::Steady;
+    }
+    let mean_index = (totals.len() - 1) as f64 / 2.0;
+    let mut covariance = 0.0;
+    let mut index_variance = 0.0;
+    for (index, total) in totals.iter().enumerate() {
+        covariance += (index as f64 - mean_index) * (*total as f64 - mean);
+        index_variance += (index as f64 - mean_index).powi(2);
+    }
+    let slope = covariance / index_variance;
+    if slope.abs() < 0.05 * mean.max(1.0) {
+        Trend::Steady
+    } else if slope > 0.0 {
+        Trend::Rising
+    } else {
+        Trend::Falling
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::counter::DeviceCount;
+    use crate::events::Breakdown;
+    use crate::history::PeriodRecord;
+    use jiff::{ToSpan, Zoned};
+
+    fn history(totals: &[usize], max: usize) -> PeriodHistory {
+        let mut history = PeriodHistory::new(totals.len());
+        let mut end = Zoned::now();
+        for total in totals {
+            history.add(PeriodRecord {
+                start: &end - 1.week(),
+                end: end.clone(),
+                total: *total,
+                max,
+                breakdown: Breakdown::default(),
+                count: DeviceCount::new(),
+            });
+            end = &end + 1.week();
+        }
+        history
+    }
+
+    #[test]
+    fn statistics_test() {
+        assert_eq!(Statistics::from_history(&PeriodHistory::new(6)), None);
+        let statistics = Statistics::from_history(&history(&[2, 4, 4, 4, 5, 5, 7, 9], 5)).unwrap();
+        assert_eq!(statistics.periods, 8);
+        assert_eq!(statistics.mean, 5.0);
+        assert_eq!(statistics.median, 4.5);
+        assert_eq!(statistics.min, 2);
+        assert_eq!(statistics.max, 9);
+        assert_eq!(statistics.standard_deviation, 2.0);
+        assert_eq!(statistics.trend, Trend::Rising);
+        assert_eq!(statistics.percent_under_limit, 75.0);
+        assert_eq!(statistics.change_from_previous, Some(2));
+        assert_eq!(
+            statistics.percent_change_from_previous,
+            Some(2.0 / 7.0 * 100.0)
+        );
+    }
+
+    #[test]
+    fn trend_test() {
+        let trend = |totals: &[usize]| get_trend(totals, history(totals, 0).get_mean().unwrap());
+        assert_eq!(trend(&[5]), Trend::Steady);
+        assert_eq!(trend(&[5, 5, 5]), Trend::Steady);
+        assert_eq!(trend(&[9, 7, 3, 1]), Trend::Falling);
+        assert_eq!(trend(&[10, 10, 11, 10]), Trend::Steady);
+        assert_eq!(trend(&[0, 0, 1, 2]), Trend::Rising);
+    }
+}
@@ -0,0 +1,236 @@
+use async_trait::async_trait;
+use autonomi::client::payment::PaymentOption;
+use autonomi::client::scratchpad::{Bytes, ScratchpadAddress, ScratchpadError};
+use autonomi::{AttoTokens, Client, PublicKey, Scratchpad, SecretKey};
+use eyre::Result;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// the operations CounterApp needs from wherever the counter scratchpad is kept,
+// so it can run against antnet or entirely in memory
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    async fn create(
+        &self,
+        owner: &SecretKey,
+        content_type: u64,
+        content: &Bytes,
+        payment_option: PaymentOption,
+    ) -> Result<(AttoTokens, ScratchpadAddress)>;
+
+    async fn get(&self, address: &ScratchpadAddress) -> Result<Scratchpad>;
+
+    async fn update(&self, owner: &SecretKey, content_type: u64, content: &Bytes) -> Result<()>;
+
+    async fn exists(&self, address: &ScratchpadAddress) -> Result<bool>;
+
+    async fn cost(&self, owner: &PublicKey) -> Result<AttoTokens>;
+}
+
+// scratchpad on antnet or a local network via an autonomi client
+pub struct ScratchpadStorage {
+    client: Client,
+}
+
+impl ScratchpadStorage {
+    pub fn new(client: Client) -> ScratchpadStorage {
+        ScratchpadStorage { client }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for ScratchpadStorage {
+    async fn create(
+        &self,
+        owner: &SecretKey,
+        content_type: u64,
+        content: &Bytes,
+        payment_option: PaymentOption,
+    ) -> Result<(AttoTokens, ScratchpadAddress)> {
+        Ok(self
+            .client
+            .scratchpad_create(owner, content_type, content, payment_option)
+            .await?)
+    }
+
+    async fn get(&self, address: &ScratchpadAddress) -> Result<Scratchpad> {
+        Ok(self.client.scratchpad_get(address).await?)
+    }
+
+    async fn update(&self, owner: &SecretKey, content_type: u64, content: &Bytes) -> Result<()> {
+        Ok(self
+            .client
+            .scratchpad_update(owner, content_type, content)
+            .await?)
+    }
+
+    async fn exists(&self, address: &ScratchpadAddress) -> Result<bool> {
+        Ok(self.client.scratchpad_check_existance(address).await?)
+    }
+
+    async fn cost(&self, owner: &PublicKey) -> Result<AttoTokens> {
+        Ok(self.client.scratchpad_cost(owner).await?)
+    }
+}
+
+// scratchpads kept in memory, clones share the same store so a test can
+// disconnect and reconnect and still see what was written
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    scratchpads: Arc<Mutex<HashMap<ScratchpadAddress, Scratchpad>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> MemoryStorage {
+        MemoryStorage::default()
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryStorage {
+    async fn create(
+        &self,
+        owner: &SecretKey,
+        content_type: u64,
+        content: &Bytes,
+        _payment_option: PaymentOption,
+    ) -> Result<(AttoTokens, ScratchpadAddress)> {
+        let address = ScratchpadAddress::new(owner.public_key());
+        let mut scratchpads = self
+            .scratchpads
+            .lock()
+            .expect("memory storage lock poisoned");
+        if scratchpads.contains_key(&address) {
+            return Err(ScratchpadError::ScratchpadAlreadyExists(address).into());
+        }
+        scratchpads.insert(address, Scratchpad::new(owner, content_type, content, 0));
+        Ok((AttoTokens::zero(), address))
+    }
+
+    async fn get(&self, address: &ScratchpadAddress) -> Result<Scratchpad> {
+        let scratchpads = self
+            .scratchpads
+            .lock()
+            .expect("memory storage lock poisoned");
+        match scratchpads.get(address) {
+            Some(scratchpad) => Ok(scratchpad.clone()),
+            None => Err(ScratchpadError::Missing.into()),
+        }
+    }
+
+    async fn update(&self, owner: &SecretKey, content_type: u64, content: &Bytes) -> Result<()> {
+        let address = ScratchpadAddress::new(owner.public_key());
+        let mut scratchpads = self
+            .scratchpads
+            .lock()
+            .expect("memory storage lock poisoned");
+        let Some(current) = scratchpads.get(&address) else {
+            return Err(ScratchpadError::CannotUpdateNewScratchpad.into());
+        };
+        let version = current.counter() + 1;
+        scratchpads.insert(
+            address,
+            Scratchpad::new(owner, content_type, content, version),
+        );
+        Ok(())
+    }
+
+    async fn exists(&self, address: &ScratchpadAddress) -> Result<bool> {
+        let scratchpads = self
+            .scratchpads
+            .lock()
+            .expect("memory storage lock poisoned");
+        Ok(scratchpads.contains_key(address))
+    }
+
+    async fn cost(&self, _owner: &PublicKey) -> Result<AttoTokens> {
+        Ok(AttoTokens::zero())
+    }
+}
+
+// wraps another backend and counts every call that goes to it, shared with
+// the background sync task so the count covers the whole session
+pub struct CountingStorage {
+    inner: Arc<dyn StorageBackend>,
+    calls: Arc<AtomicU64>,
+}
+
+impl CountingStorage {
+    pub fn new(inner: Arc<dyn StorageBackend>, calls: Arc<AtomicU64>) -> CountingStorage {
+        CountingStorage { inner, calls }
+    }
+
+    fn count(&self) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[async_trait]
+impl StorageBackend for CountingStorage {
+    async fn create(
+        &self,
+        owner: &SecretKey,
+        content_type: u64,
+        content: &Bytes,
+        payment_option: PaymentOption,
+    ) -> Result<(AttoTokens, ScratchpadAddress)> {
+        self.count();
+        self.inner
+            .create(owner, content_type, content, payment_option)
+            .await
+    }
+
+    async fn get(&self, address: &ScratchpadAddress) -> Result<Scratchpad> {
+        self.count();
+        self.inner.get(address).await
+    }
+
+    async fn update(&self, owner: &SecretKey, content_type: u64, content: &Bytes) -> Result<()> {
+        self.count();
+        self.inner.update(owner, content_type, content).await
+    }
+
+    async fn exists(&self, address: &ScratchpadAddress) -> Result<bool> {
+        self.count();
+        self.inner.exists(address).await
+    }
+
+    async fn cost(&self, owner: &PublicKey) -> Result<AttoTokens> {
+        self.count();
+        self.inner.cost(owner).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use autonomi::client::payment::Receipt;
+
+    #[tokio::test]
+    async fn memory_storage_test() {
+        let storage = MemoryStorage::new();
+        let key = SecretKey::random();
+        let address = ScratchpadAddress::new(key.public_key());
+        assert!(!storage.exists(&address).await.unwrap());
+        assert!(storage.update(&key, 99, &Bytes::from("1")).await.is_err());
+        storage
+            .create(&key, 99, &Bytes::from("1"), Receipt::new().into())
+            .await
+            .unwrap();
+        assert!(storage.exists(&address).await.unwrap());
+        assert!(storage
+            .create(&key, 99, &Bytes::from("1"), Receipt::new().into())
+            .await
+            .is_err());
+        storage.update(&key, 99, &Bytes::from("2")).await.unwrap();
+        let scratchpad = storage.clone().get(&address).await.unwrap();
+        assert_eq!(scratchpad.counter(), 1);
+        assert_eq!(scratchpad.decrypt_data(&key).unwrap(), Bytes::from("2"));
+        let calls = Arc::new(AtomicU64::new(0));
+        let counting = CountingStorage::new(Arc::new(storage), calls.clone());
+        counting.get(&address).await.unwrap();
+        assert!(counting.exists(&address).await.unwrap());
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,335 @@
+use crate::collection::{Change, CounterCollection};
+use crate::payload::{decode_counters, encode_counters};
+use crate::retry::RetryPolicy;
+use crate::storage::StorageBackend;
+use autonomi::client::scratchpad::ScratchpadAddress;
+use autonomi::{Scratchpad, SecretKey};
+use eyre::{eyre, Result};
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::time::{timeout, Duration};
+
+// a snapshot of the counters to upload with the journal it includes, so
+// only that much is cleared once it is on antnet and it can be replayed if
+// another device has uploaded since the scratchpad seen. without counters it
+// asks for the latest scratchpad instead
+struct SyncRequest {
+    id: u64,
+    counters: Option<CounterCollection>,
+    journal: Vec<Change>,
+    seen: u64,       // counter of the scratchpad the snapshot was made from
+    read_back: bool, // false while battery saver trusts antnet to take the write
+}
+
+impl SyncRequest {
+    // an upload is never dropped for a refresh queued after it
+    fn coalesce(self, newer: SyncRequest) -> SyncRequest {
+        match newer.counters {
+            Some(_) => newer,
+            None => SyncRequest {
+                id: newer.id,
+                ..self
+            },
+        }
+    }
+}
+
+// scratchpad is None if the upload wasn't read back, counters is what was
+// uploaded if another device's changes had to be merged in first
+pub enum SyncResult {
+    Synced {
+        scratchpad: Option<Box<Scratchpad>>,
+        counters: Option<Box<CounterCollection>>,
+        journal_len: usize,
+    },
+    Refreshed(Box<Scratchpad>),
+    Failed(String),
+}
+
+// uploads and refreshes on a tokio task of its own so the front end never
+// waits on antnet, anything queued while an upload is going on or within the
+// debounce of the last change is coalesced into the latest snapshot
+pub struct SyncTask {
+    requests: UnboundedSender<SyncRequest>,
+    results: UnboundedReceiver<(u64, SyncResult)>,
+    queued: u64, // id of the latest request
+    done: u64,   // id of the latest request a result has come back for
+}
+
+impl SyncTask {
+    pub fn spawn(
+        storage: Arc<dyn StorageBackend>,
+        key: SecretKey,
+        device_id: String,
+        content_type: u64,
+        read: RetryPolicy,
+        verify: RetryPolicy,
+        debounce: Duration,
+    ) -> SyncTask {
+        let (requests, mut pending) = mpsc::unbounded_channel::<SyncRequest>();
+        let (finished, results) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // counter of the latest scratchpad written here, a refresh only
+            // counts once the app has taken it, and then comes in a request
+            let mut seen = 0;
+            while let Some(mut request) = pending.recv().await {
+                // waits until changes stop coming, or the queue is closed
+                while !debounce.is_zero() {
+                    match timeout(debounce, pending.recv()).await {
+                        Ok(Some(newer)) => request = request.coalesce(newer),
+                        _ => break,
+                    }
+                }
+                while let Ok(newer) = pending.try_recv() {
+                    request = request.coalesce(newer);
+                }
+                seen = seen.max(request.seen);
+                let result = match &request.counters {
+                    Some(counters) => {
+                        let verify = request.read_back.then_some(&verify);
+                        let uploader = Uploader {
+                            storage: &*storage,
+                            key: &key,
+                            device_id: &device_id,
+                            content_type,
+                        };
+                        match uploader
+                            .upload(verify, counters, &request.journal, &mut seen)
+                            .await
+                        {
+                            Ok((scratchpad, counters)) => SyncResult::Synced {
+                                scratchpad: scratchpad.map(Box::new),
+                                counters: counters.map(Box::new),
+                                journal_len: request.journal.len(),
+                            },
+                            Err(error) => SyncResult::Failed(error.to_string()),
+                        }
+                    }
+                    None => {
+                        let address = ScratchpadAddress::new(key.public_key());
+                        match read
+                            .retry("Reading scratchpad", || storage.get(&address))
+                            .await
+                        {
+                            Ok(scratchpad) => SyncResult::Refreshed(Box::new(scratchpad)),
+                            Err(error) => SyncResult::Failed(error.to_string()),
+                        }
+                    }
+                };
+                if finished.send((request.id, result)).is_err() {
+                    break; // nobody is listening any more
+                }
+            }
+        });
+        SyncTask {
+            requests,
+            results,
+            queued: 0,
+            done: 0,
+        }
+    }
+
+    pub fn queue(
+        &mut self,
+        counters: CounterCollection,
+        journal: Vec<Change>,
+        seen: u64,
+        read_back: bool,
+    ) -> Result<()> {
+        self.send(SyncRequest {
+            id: 0,
+            counters: Some(counters),
+            journal,
+            seen,
+            read_back,
+        })
+    }
+
+    // asks for the latest scratchpad, e.g. to pick up other devices' changes
+    pub fn refresh(&mut self) -> Result<()> {
+        self.send(SyncRequest {
+            id: 0,
+            counters: None,
+            journal: Vec::new(),
+            seen: 0,
+            read_back: false,
+        })
+    }
+
+    fn send(&mut self, request: SyncRequest) -> Result<()> {
+        self.queued += 1;
+        let request = SyncRequest {
+            id: self.queued,
+            ..request
+        };
+        self.requests
+            .send(request)
+            .map_err(|_| eyre!("Sync task has stopped"))
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.done == self.queued
+    }
+
+    // doesn't wait, None if nothing has finished since last time
+    pub fn try_result(&mut self) -> Option<SyncResult> {
+        let (id, result) = self.results.try_recv().ok()?;
+        self.done = id;
+        Some(result)
+    }
+
+    // waits for the next result, None if nothing is queued
+    pub async fn next_result(&mut self) -> Option<SyncResult> {
+        if self.is_idle() {
+            return None;
+        }
+        let (id, result) = self.results.recv().await?;
+        self.done = id;
+        Some(result)
+    }
+}
+
+// what an upload needs that stays the same for the life of the task
+struct Uploader<'a> {
+    storage: &'a dyn StorageBackend,
+    key: &'a SecretKey,
+    device_id: &'a str,
+    content_type: u64,
+}
+
+impl Uploader<'_> {
+    // writes the counters then, given a verify policy, reads them back until
+    // antnet returns them. if another device has uploaded since the scratchpad
+    // seen its counters are merged in first rather than written over, and
+    // returned as what was uploaded
+    async fn upload(
+        &self,
+        verify: Option<&RetryPolicy>,
+        counters: &CounterCollection,
+        journal: &[Change],
+        seen: &mut u64,
+    ) -> Result<(Option<Scratchpad>, Option<CounterCollection>)> {
+        let address = ScratchpadAddress::new(self.key.public_key());
+        let network = self.storage.get(&address).await?;
+        let merged = match network.counter() > *seen {
+            true => {
+                let mut merged = decode_counters(&network.decrypt_data(self.key)?)?;
+                merged.replay(journal, counters, self.device_id)?;
+                Some(merged)
+            }
+            false => None,
+        };
+        let counters = merged.as_ref().unwrap_or(counters);
+        self.storage
+            .update(self.key, self.content_type, &encode_counters(counters)?)
+            .await?;
+        *seen = network.counter() + 1;
+        let Some(verify) = verify else {
+            return Ok((None, merged));
+        };
+        let scratchpad = verify
+            .retry("Checking antnet count matches", || async {
+                let scratchpad = self.storage.get(&address).await?;
+                match decode_counters(&scratchpad.decrypt_data(self.key)?)? == *counters {
+                    true => Ok(scratchpad),
+                    false => Err(eyre!("Antnet count doesn't match yet")),
+                }
+            })
+            .await?;
+        *seen = scratchpad.counter();
+        Ok((Some(scratchpad), merged))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collection::{Change, DEFAULT_COUNTER_NAME};
+    use crate::counter::Operation;
+    use crate::storage::MemoryStorage;
+    use autonomi::client::payment::Receipt;
+
+    #[tokio::test]
+    async fn sync_task_test() {
+        let storage = MemoryStorage::new();
+        let key = SecretKey::random();
+        let mut counters = CounterCollection::new().unwrap();
+        storage
+            .create(
+                &key,
+                99,
+                &encode_counters(&counters).unwrap(),
+                Receipt::new().into(),
+            )
+            .await
+            .unwrap();
+        let verify = RetryPolicy {
+            max_attempts: 2,
+            initial_delay_ms: 10,
+            backoff_factor: 2.0,
+            jitter: 0.0,
+            deadline_secs: 1,
+            wait_before_first_attempt: true,
+        };
+        let read = RetryPolicy {
+            wait_before_first_attempt: false,
+            ..verify.clone()
+        };
+        let debounce = Duration::from_millis(20);
+        let storage_arc = Arc::new(storage.clone());
+        let mut task = SyncTask::spawn(
+            storage_arc,
+            key.clone(),
+            "phone".to_string(),
+            99,
+            read,
+            verify,
+            debounce,
+        );
+        assert!(task.is_idle());
+        assert!(task.try_result().is_none());
+        let increment = Change::Update {
+            name: DEFAULT_COUNTER_NAME.to_string(),
+            operation: Operation::Increment,
+        };
+        for journal_len in 1..=3 {
+            counters.apply(&increment, "phone").unwrap();
+            let journal = vec![increment.clone(); journal_len];
+            task.queue(counters.clone(), journal, 0, true).unwrap();
+            // a refresh doesn't replace an upload queued before it
+            task.refresh().unwrap();
+        }
+        assert!(!task.is_idle());
+        // changes within the debounce are coalesced into one upload
+        let Some(SyncResult::Synced { journal_len, .. }) = task.next_result().await else {
+            panic!("sync failed");
+        };
+        assert_eq!(journal_len, 3);
+        assert!(task.is_idle());
+        counters.apply(&increment, "phone").unwrap();
+        task.queue(counters.clone(), vec![increment.clone(); 4], 0, true)
+            .unwrap();
+        // the task's own uploads aren't taken for another device's
+        let last = task.next_result().await;
+        let Some(SyncResult::Synced {
+            journal_len,
+            counters: None,
+            ..
+        }) = last
+        else {
+            panic!("sync failed");
+        };
+        assert_eq!(journal_len, 4);
+        let address = ScratchpadAddress::new(key.public_key());
+        let scratchpad = storage.get(&address).await.unwrap();
+        assert_eq!(
+            decode_counters(&scratchpad.decrypt_data(&key).unwrap()).unwrap(),
+            counters
+        );
+        task.refresh().unwrap();
+        let Some(SyncResult::Refreshed(refreshed)) = task.next_result().await else {
+            panic!("refresh failed");
+        };
+        assert_eq!(refreshed.counter(), scratchpad.counter());
+    }
+}
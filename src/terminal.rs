@@ -0,0 +1,64 @@
+use ant_counter::recovery::{self, Mnemonic};
+use ant_counter::Prompt;
+use autonomi::AttoTokens;
+use eyre::Result;
+use std::env;
+use std::io::{self, Write};
+
+// so scripts and cron jobs can unlock the key without a prompt
+pub const PASSPHRASE_ENV_VAR: &str = "ANT_COUNTER_PASSPHRASE";
+
+// asks on the terminal, secrets without echoing them
+pub struct TerminalPrompt;
+
+impl Prompt for TerminalPrompt {
+    fn ask_for_passphrase(&self) -> io::Result<String> {
+        if let Ok(passphrase) = env::var(PASSPHRASE_ENV_VAR) {
+            return Ok(passphrase);
+        }
+        rpassword::prompt_password("Enter key file passphrase: ")
+    }
+
+    // asks twice so a typo doesn't lock the key away
+    fn ask_for_new_passphrase(&self) -> io::Result<String> {
+        if let Ok(passphrase) = env::var(PASSPHRASE_ENV_VAR) {
+            return Ok(passphrase);
+        }
+        loop {
+            let passphrase = rpassword::prompt_password("Enter a passphrase for the key file: ")?;
+            if passphrase.is_empty() {
+                println!("Passphrase can't be empty");
+                continue;
+            }
+            if passphrase == rpassword::prompt_password("Enter it again: ")? {
+                return Ok(passphrase);
+            }
+            println!("Passphrases didn't match");
+        }
+    }
+
+    fn show_recovery_words(&self, mnemonic: &Mnemonic) -> io::Result<()> {
+        println!("Write down these recovery words, they are the only way to get the counter back if the key file is lost:");
+        println!("{mnemonic}");
+        println!("Press enter once they are written down, they won't be shown again");
+        io::stdin().read_line(&mut String::new())?;
+        print!("\x1B[2J\x1B[1;1H"); // clear the screen so the words don't stay in view
+        io::stdout().flush()
+    }
+
+    fn confirm_cost(&self, cost: &AttoTokens) -> io::Result<bool> {
+        println!("Type yes to confirm creation of scratchpad at cost: {cost}:");
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        Ok(input.trim() == "yes")
+    }
+
+    fn ask_for_wallet_private_key(&self) -> io::Result<String> {
+        rpassword::prompt_password("Enter wallet private key: ")
+    }
+}
+
+// hidden like a passphrase as the words are as good as the key
+pub fn ask_for_mnemonic() -> Result<Mnemonic> {
+    recovery::parse_mnemonic(&rpassword::prompt_password("Enter the recovery words: ")?)
+}
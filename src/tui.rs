@@ -0,0 +1,234 @@
+use ant_counter::messages;
+use ant_counter::{CounterApp, Error, SyncResult};
+use eyre::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Bar, BarChart, BarGroup, Block, Gauge, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+const KEYS_HELP: &str = "(i) increment  (u) undo  (r) reset  (m) set max  (c) connect  (q) quit";
+
+// what the screen shows besides the counter itself
+#[derive(Default)]
+struct TuiState {
+    max_input: Option<String>, // digits typed so far while setting the max
+    sync_status: String,
+}
+
+// takes over the terminal until q is pressed, the counter has to be loaded
+// and connected (or not) already as prompts can't be answered in here
+pub async fn run(counter_app: &mut CounterApp) -> Result<()> {
+    // anything printed would land on top of the screen
+    let (sink, messages) = mpsc::channel();
+    messages::set_sink(Some(sink));
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, counter_app, &messages).await;
+    ratatui::restore();
+    messages::set_sink(None);
+    if counter_app.is_syncing() {
+        println!("Waiting for changes to reach antnet...");
+    }
+    if let Some(SyncResult::Failed(error)) = counter_app.finish_sync().await? {
+        println!("Sync failed: {error}, changes are kept locally");
+    }
+    result
+}
+
+async fn run_loop(
+    terminal: &mut DefaultTerminal,
+    counter_app: &mut CounterApp,
+    messages: &Receiver<String>,
+) -> Result<()> {
+    let mut state = TuiState::default();
+    loop {
+        // the latest message from the counter, e.g. a retry, shows as the status
+        while let Ok(message) = messages.try_recv() {
+            state.sync_status = message;
+        }
+        while let Some(result) = counter_app.poll_sync()? {
+            state.sync_status = match result {
+                SyncResult::Refreshed(_) => continue,
+                SyncResult::Synced { .. } if counter_app.journal.is_empty() => "Synced".to_string(),
+                SyncResult::Synced { .. } => {
+                    format!("{} changes waiting to sync", counter_app.journal.len())
+                }
+                SyncResult::Failed(error) => format!("Sync failed: {error}"),
+            };
+        }
+        terminal.draw(|frame| draw(frame, counter_app, &state))?;
+        // wakes up often enough to show a finished sync or a period ending
+        if event::poll(Duration::from_millis(250))? {
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if let Some(max_input) = &mut state.max_input {
+                match key.code {
+                    KeyCode::Char(digit) if digit.is_ascii_digit() => max_input.push(digit),
+                    KeyCode::Backspace => {
+                        max_input.pop();
+                    }
+                    KeyCode::Enter => {
+                        if let Ok(max) = max_input.parse() {
+                            counter_app.set_max(max)?;
+                            state.max_input = None;
+                            sync(counter_app, &mut state)?;
+                        }
+                    }
+                    KeyCode::Esc => state.max_input = None,
+                    _ => (),
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Char('i') => counter_app.increment()?,
+                KeyCode::Char('u') => {
+                    if !counter_app.undo()? {
+                        state.sync_status = "Nothing to undo".to_string();
+                        continue;
+                    }
+                }
+                KeyCode::Char('r') => counter_app.reset()?,
+                KeyCode::Char('m') => {
+                    state.max_input = Some(String::new());
+                    continue;
+                }
+                KeyCode::Char('c') => {
+                    if counter_app.get_counter_state() != "Connected" {
+                        match counter_app.connect(false).await {
+                            Err(error @ Error::ClientInit(_)) => {
+                                state.sync_status = error.to_string()
+                            }
+                            result => result?,
+                        }
+                    }
+                    continue;
+                }
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                _ => continue,
+            }
+            sync(counter_app, &mut state)?;
+        }
+        if counter_app.counters.reset_if_next_period()? {
+            sync(counter_app, &mut state)?;
+        }
+    }
+}
+
+// the change is saved locally and shown straight away, the run loop picks up
+// how the upload went
+fn sync(counter_app: &mut CounterApp, state: &mut TuiState) -> Result<()> {
+    counter_app.queue_sync()?;
+    if counter_app.is_syncing() {
+        state.sync_status = "Syncing to antnet...".to_string();
+    }
+    Ok(())
+}
+
+fn draw(frame: &mut Frame, counter_app: &CounterApp, state: &TuiState) {
+    let counter = counter_app.counter();
+    let [remaining_area, gauge_area, history_area, status_area, keys_area] = Layout::vertical([
+        Constraint::Length(3),
+        Constraint::Length(3),
+        Constraint::Min(6),
+        Constraint::Length(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    let remaining = Paragraph::new(format!(
+        "Remaining: {} of {}, resets {}",
+        counter.number_remaining(),
+        counter.max,
+        counter.reset_zoned_date_time.strftime("%a %d %b %H:%M"),
+    ))
+    .block(Block::bordered().title(format!(" {} ", counter_app.selected)));
+    frame.render_widget(remaining, remaining_area);
+
+    let ratio = match counter.max {
+        0 if counter.count() > 0 => 1.0,
+        0 => 0.0,
+        max => (counter.count() as f64 / max as f64).min(1.0),
+    };
+    let colour = if counter.number_remaining() < 0 {
+        Color::Red
+    } else {
+        Color::Green
+    };
+    let gauge = Gauge::default()
+        .block(Block::bordered().title(" Used "))
+        .gauge_style(Style::default().fg(colour))
+        .ratio(ratio)
+        .label(format!("{} / {}", counter.count(), counter.max));
+    frame.render_widget(gauge, gauge_area);
+
+    let bars: Vec<Bar> = counter
+        .history
+        .records()
+        .iter()
+        .map(|record| {
+            Bar::default()
+                .value(record.total as u64)
+                .label(Line::from(record.start.strftime("%d %b").to_string()))
+        })
+        .collect();
+    let history = BarChart::default()
+        .block(Block::bordered().title(" History "))
+        .bar_width(6)
+        .bar_gap(1)
+        .data(BarGroup::default().bars(&bars));
+    frame.render_widget(history, history_area);
+
+    let status = match &state.max_input {
+        Some(max_input) => format!("New max: {max_input}_  (enter to set, esc to cancel)"),
+        None => format!(
+            "{}  {}  ({} network calls)",
+            counter_app.get_counter_state(),
+            state.sync_status,
+            counter_app.get_network_calls()
+        ),
+    };
+    frame.render_widget(
+        Paragraph::new(status).block(Block::bordered().title(" Status ")),
+        status_area,
+    );
+    frame.render_widget(Paragraph::new(KEYS_HELP), keys_area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    #[test]
+    fn draw_test() {
+        let mut counter_app = CounterApp::new().unwrap();
+        counter_app.set_max(4).unwrap();
+        counter_app.increment().unwrap();
+        let mut terminal = Terminal::new(TestBackend::new(80, 20)).unwrap();
+        let state = TuiState {
+            max_input: None,
+            sync_status: "Synced".to_string(),
+        };
+        terminal
+            .draw(|frame| draw(frame, &counter_app, &state))
+            .unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("Remaining: 3 of 4"));
+        assert!(screen.contains("1 / 4"));
+        assert!(screen.contains("Initiating  Synced  (0 network calls)"));
+    }
+}